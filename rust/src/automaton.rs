@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    VonNeumann,
+    Moore,
+    Custom(Vec<(i64, i64)>)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    Bounded,
+    Toroidal,
+    Reflecting
+}
+
+// Every step each cell gains energy, any cell above the threshold flashes
+// once giving energy to its neighbours, and flashed cells are reset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub gain : u32,
+    pub threshold : u32,
+    pub spread : u32,
+    pub reset : u32,
+    pub neighbourhood : Neighbourhood,
+    pub boundary : Boundary
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Grid {
+    pub energy : Vec<Vec<u32>>
+}
+
+#[derive(Clone, Debug)]
+pub struct Automaton {
+    grid : Grid,
+    rules : Rules
+}
+
+// Cells in the order they flashed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Step {
+    pub flashed : Vec<(usize, usize)>
+}
+
+// The grid after step start is seen again after step start + length. Sync
+// steps are those where every cell flashed, counted from one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub start : usize,
+    pub length : usize,
+    pub first_sync : Option<usize>,
+    pub sync_period : Option<usize>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationError {
+    StepLimit(usize),
+    NeverSynchronises(Cycle)
+}
+
+enum Outcome<T> {
+    Stopped(T),
+    Repeated(Cycle)
+}
+
+impl Neighbourhood {
+    fn offsets(&self) -> Vec<(i64, i64)> {
+        match self {
+            Neighbourhood::VonNeumann => vec!((-1,0), (0,-1), (0,1), (1,0)),
+            Neighbourhood::Moore => vec!((-1,-1), (-1,0), (-1,1), (0,-1), (0,1), (1,-1), (1,0), (1,1)),
+            Neighbourhood::Custom(offsets) => offsets.clone()
+        }
+    }
+}
+
+impl Boundary {
+    fn wrap(&self, index : i64, size : usize) -> Option<usize> {
+        let size = size as i64;
+        let wrapped = match self {
+            Boundary::Bounded => index,
+            Boundary::Toroidal => index.rem_euclid(size),
+            Boundary::Reflecting => if index < 0 {
+                -index
+            } else if index >= size {
+                2 * (size - 1) - index
+            } else {
+                index
+            }
+        };
+        if wrapped >= 0 && wrapped < size {
+            Some(wrapped as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Rules {
+    pub fn octopus() -> Rules {
+        Rules {
+            gain : 1,
+            threshold : 9,
+            spread : 1,
+            reset : 0,
+            neighbourhood : Neighbourhood::Moore,
+            boundary : Boundary::Bounded
+        }
+    }
+}
+
+impl Grid {
+    pub fn size(&self) -> (usize, usize) {
+        (self.energy.len(), self.energy.first().map(|row| row.len()).unwrap_or(0))
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (h, w) = self.size();
+        (0..h).flat_map(move |i| (0..w).map(move |j| (i,j)))
+    }
+}
+
+impl Automaton {
+    pub fn new(energy : Vec<Vec<u32>>, rules : Rules) -> Automaton {
+        Automaton { grid : Grid { energy : energy }, rules : rules }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn cell_count(&self) -> usize {
+        let (h, w) = self.grid.size();
+        h * w
+    }
+
+    fn neighbours(&self, i : usize, j : usize) -> Vec<(usize, usize)> {
+        let (h, w) = self.grid.size();
+        self.rules.neighbourhood.offsets().into_iter().filter_map(
+            |(di, dj)| Some((
+                self.rules.boundary.wrap(i as i64 + di, h)?,
+                self.rules.boundary.wrap(j as i64 + dj, w)?
+            ))
+        ).collect()
+    }
+
+    pub fn step(&mut self) -> Step {
+        let (h, w) = self.grid.size();
+        let mut flashed = vec!(vec!(false; w); h);
+        let mut to_flash : VecDeque<(usize, usize)> = VecDeque::new();
+        for (i, j) in self.grid.cells() {
+            let energy = &mut self.grid.energy[i][j];
+            *energy = energy.saturating_add(self.rules.gain);
+            if *energy > self.rules.threshold {
+                flashed[i][j] = true;
+                to_flash.push_back((i,j));
+            }
+        }
+        let mut step = Step::default();
+        while let Some((i, j)) = to_flash.pop_front() {
+            step.flashed.push((i,j));
+            for (n_i, n_j) in self.neighbours(i, j) {
+                let energy = &mut self.grid.energy[n_i][n_j];
+                *energy = energy.saturating_add(self.rules.spread);
+                if *energy > self.rules.threshold && !flashed[n_i][n_j] {
+                    flashed[n_i][n_j] = true;
+                    to_flash.push_back((n_i,n_j));
+                }
+            }
+        }
+        for (i, j) in step.flashed.iter() {
+            self.grid.energy[*i][*j] = self.rules.reset;
+        }
+        step
+    }
+
+    pub fn run(&mut self, steps : usize) -> Vec<Step> {
+        (0..steps).map(|_| self.step()).collect()
+    }
+
+    // Remembers every grid seen, so stops as soon as any state repeats or
+    // when stop gives a value for a step where every cell flashed.
+    fn explore<T, F>(&mut self, max_steps : usize, mut stop : F) -> Result<Outcome<T>, SimulationError>
+        where F : FnMut(usize) -> Option<T>
+    {
+        let total = self.cell_count();
+        let mut history : HashMap<Grid, usize> = HashMap::new();
+        history.insert(self.grid.clone(), 0);
+        let mut syncs : Vec<usize> = Vec::new();
+        for step in 1..(max_steps+1) {
+            if self.step().flashed.len() == total {
+                if let Some(value) = stop(step) {
+                    return Ok(Outcome::Stopped(value));
+                }
+                syncs.push(step);
+            }
+            if let Some(start) = history.get(&self.grid) {
+                let length = step - start;
+                return Ok(Outcome::Repeated(Cycle {
+                    start : *start,
+                    length : length,
+                    first_sync : syncs.first().cloned(),
+                    sync_period : if syncs.iter().any(|sync| sync > start) { Some(length) } else { None }
+                }));
+            }
+            history.insert(self.grid.clone(), step);
+        }
+        Err(SimulationError::StepLimit(max_steps))
+    }
+
+    pub fn find_cycle(&mut self, max_steps : usize) -> Result<Cycle, SimulationError> {
+        match self.explore(max_steps, |_| None::<Infallible>)? {
+            Outcome::Repeated(cycle) => Ok(cycle),
+            Outcome::Stopped(never) => match never {}
+        }
+    }
+
+    // The first step where every cell flashes
+    pub fn run_until_sync(&mut self, max_steps : usize) -> Result<usize, SimulationError> {
+        match self.explore(max_steps, Some)? {
+            Outcome::Stopped(step) => Ok(step),
+            Outcome::Repeated(cycle) => Err(SimulationError::NeverSynchronises(cycle))
+        }
+    }
+}
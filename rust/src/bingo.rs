@@ -1,61 +1,174 @@
-use itertools::Itertools;
-use std::convert::TryInto;
+use std::collections::{HashMap, BTreeSet};
 
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
 
+// Working out expected draws exactly takes time and memory doubling with
+// each winning line on a board, so boards with more lines are refused.
+pub const MAX_EXACT_LINES : usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WinRule {
+    Rows,
+    Columns,
+    Diagonals,
+    FullCard,
+    FourCorners,
+    Pattern(Vec<Vec<bool>>)
+}
+
+#[derive(Clone)]
 struct Board {
-    elements : [[Option<u32>; 5]; 5]
+    elements : Vec<Vec<Option<u32>>>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Drawn(u32),
+    Marked { board : usize },
+    Won { board : usize, score : u32 }
+}
+
+// Draws are counted from one, so draw is how many numbers had been called
+// when the board won.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub board : usize,
+    pub draw : usize,
+    pub number : u32,
+    pub score : u32
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Simulation {
+    pub events : Vec<Event>,
+    pub finishing_order : Vec<Placement>,
+    pub never_won : Vec<usize>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WinProbability {
+    pub board : usize,
+    pub first : f64,
+    pub last : f64
 }
 
+#[derive(Clone)]
 pub struct Game {
     numbers : Vec<u32>,
-    boards : Vec<Board>
+    boards : Vec<Board>,
+    rules : Vec<WinRule>
 }
 
-fn parse_row(line : &String) -> Option<[Option<u32>; 5]> {
-    let row : Vec<Option<u32>> = line.split(" ").map(
+fn parse_row(line : &str) -> Option<Vec<Option<u32>>> {
+    let row : Vec<Option<u32>> = line.split_whitespace().map(
         |element| element.parse::<u32>().ok()
-    ).filter(
-        |res| res.is_some()
     ).collect();
-    row.try_into().ok()
+    if row.is_empty() || row.iter().any(|elem| elem.is_none()) {
+        None
+    } else {
+        Some(row)
+    }
+}
+
+impl WinRule {
+    fn lines(&self, h : usize, w : usize) -> Vec<Vec<(usize, usize)>> {
+        match self {
+            WinRule::Rows => (0..h).map(
+                |i| (0..w).map(|j| (i,j)).collect()
+            ).collect(),
+            WinRule::Columns => (0..w).map(
+                |j| (0..h).map(|i| (i,j)).collect()
+            ).collect(),
+            WinRule::Diagonals => {
+                if h == w {
+                    vec!(
+                        (0..h).map(|i| (i,i)).collect(),
+                        (0..h).map(|i| (i,w-1-i)).collect()
+                    )
+                } else {
+                    Vec::new()
+                }
+            },
+            WinRule::FullCard => vec!(
+                (0..h).flat_map(|i| (0..w).map(move |j| (i,j))).collect()
+            ),
+            WinRule::FourCorners => vec!(
+                vec!((0,0), (0,w-1), (h-1,0), (h-1,w-1))
+            ),
+            WinRule::Pattern(mask) => {
+                let fits = mask.len() == h && mask.iter().all(|row| row.len() == w);
+                let cells : Vec<(usize, usize)> = mask.iter().enumerate().flat_map(
+                    |(i, row)| row.iter().enumerate().filter(
+                        |(_, set)| **set
+                    ).map(move |(j, _)| (i,j))
+                ).collect();
+                if fits && !cells.is_empty() {
+                    vec!(cells)
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
 }
 
 impl Board {
-    pub fn from_lines<'a, I>(lines_iter : I) -> Option<Board> 
+    pub fn from_lines<'a, I>(lines_iter : I) -> Option<Board>
         where I : Iterator<Item = &'a String>
     {
-        let elements : Vec<[Option<u32>;5]> = lines_iter.filter_map(
+        let elements : Vec<Vec<Option<u32>>> = lines_iter.map(
             |line| parse_row(line)
-        ).collect();
-        if let Some(array) = elements.try_into().ok() {
-            Some(Board {elements : array} )
+        ).collect::<Option<Vec<Vec<Option<u32>>>>>()?;
+        let width = elements.first()?.len();
+        if elements.iter().all(|row| row.len() == width) {
+            Some(Board {elements : elements})
         } else {
             None
         }
     }
 
-    fn mark_number(&mut self, number : u32) {
+    fn size(&self) -> (usize, usize) {
+        (self.elements.len(), self.elements[0].len())
+    }
+
+    fn mark_number(&mut self, number : u32) -> bool {
+        let mut marked = false;
         for row in self.elements.iter_mut() {
             for elem in row.iter_mut() {
-                if elem.contains(&number) {
-                    *elem = None
+                if *elem == Some(number) {
+                    *elem = None;
+                    marked = true;
                 }
             }
         }
+        marked
     }
 
-    fn has_win(&self) -> Option<u32> {
-        for i in 0..5 {
-            if (0..5).all(|j| self.elements[i][j].is_none())  {
-                return Some(self.score());
-            }
-        }
-        for j in 0..5 {
-            if (0..5).all(|i| self.elements[i][j].is_none())  {
-                return Some(self.score());
-            }
+    fn has_win(&self, rules : &[WinRule]) -> Option<u32> {
+        let (h, w) = self.size();
+        let won = rules.iter().flat_map(
+            |rule| rule.lines(h, w)
+        ).any(
+            |line| line.iter().all(|(i,j)| self.elements[*i][*j].is_none())
+        );
+        if won {
+            Some(self.score())
+        } else {
+            None
         }
-        None
+    }
+
+    fn winning_sets(&self, rules : &[WinRule], pool : &BTreeSet<u32>) -> Vec<BTreeSet<u32>> {
+        let (h, w) = self.size();
+        rules.iter().flat_map(
+            |rule| rule.lines(h, w)
+        ).map(
+            |line| line.iter().filter_map(|(i,j)| self.elements[*i][*j]).collect::<BTreeSet<u32>>()
+        ).filter(
+            |numbers| numbers.is_subset(pool)
+        ).collect()
     }
 
     fn score(&self) -> u32 {
@@ -74,52 +187,207 @@ impl Game {
                 |part| part.parse::<u32>().ok()
             ).rev().collect()
         ) {
-            let boards = lines.iter().skip(1).chunks(6).into_iter().filter_map(
-                |board_lines| Board::from_lines(board_lines.skip(1))
-            ).collect();
-            Some (Game {numbers : numbers, boards: boards})
+            let mut boards : Vec<Board> = Vec::new();
+            let mut board_lines : Vec<&String> = Vec::new();
+            for line in lines.iter().skip(1).chain(std::iter::once(&String::new())) {
+                if line.trim().is_empty() {
+                    if !board_lines.is_empty() {
+                        boards.push(Board::from_lines(board_lines.drain(..))?);
+                    }
+                } else {
+                    board_lines.push(line);
+                }
+            }
+            Some (Game {numbers : numbers, boards: boards, rules : vec!(WinRule::Rows, WinRule::Columns)})
         } else {
             None
         }
     }
 
-    fn mark_number(&mut self, number : u32) -> Vec<u32> {
-        let mut new_scores : Vec<u32> = Vec::new();
-        for board in self.boards.iter_mut() {
-            let already_won = board.has_win().is_some();
-            board.mark_number(number);
-            if !already_won {
-                if let Some(new_score) = board.has_win() {
-                    new_scores.push(new_score);
+    pub fn with_rules(mut self, rules : Vec<WinRule>) -> Game {
+        self.rules = rules;
+        self
+    }
+
+    // Plays on copies of the boards and numbers so the game can be replayed
+    pub fn simulate(&self) -> Simulation {
+        let mut simulation = Simulation::default();
+        let mut boards = self.boards.clone();
+        let mut numbers = self.numbers.clone();
+        let mut won = vec!(false; boards.len());
+        let mut draw = 0;
+        while let Some(number) = numbers.pop() {
+            draw += 1;
+            simulation.events.push(Event::Drawn(number));
+            for (index, board) in boards.iter_mut().enumerate() {
+                if board.mark_number(number) {
+                    simulation.events.push(Event::Marked { board : index });
+                }
+                if !won[index] {
+                    if let Some(score) = board.has_win(&self.rules) {
+                        won[index] = true;
+                        simulation.events.push(Event::Won { board : index, score : score * number });
+                        simulation.finishing_order.push(Placement {
+                            board : index,
+                            draw : draw,
+                            number : number,
+                            score : score * number
+                        });
+                    }
                 }
             }
+            if won.iter().all(|has_won| *has_won) {
+                break;
+            }
         }
-        new_scores
+        simulation.never_won = (0..boards.len()).filter(|index| !won[*index]).collect();
+        simulation
+    }
+
+    pub fn play_first(&self) -> u32 {
+        self.simulate().finishing_order.first().map(
+            |placement| placement.score
+        ).unwrap_or(0)
     }
 
-    fn no_boards(&self) -> bool {
-        self.boards.iter().all(
-            |board| board.has_win().is_some()
-        )
+    pub fn play_last(&self) -> u32 {
+        let simulation = self.simulate();
+        if simulation.never_won.is_empty() {
+            simulation.finishing_order.last().map(
+                |placement| placement.score
+            ).unwrap_or(0)
+        } else {
+            0
+        }
     }
 
-    pub fn play_first(&mut self) -> u32 {
-        while let Some(number) = self.numbers.pop() {
-            let new_scores = self.mark_number(number);
-            if new_scores.len() > 0 {
-                return new_scores[0] * number;
+    // None without any trials to take the chances from
+    pub fn win_probabilities(&self, trials : usize, seed : u64) -> Option<Vec<WinProbability>> {
+        if trials == 0 {
+            return None;
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut first_counts = vec!(0; self.boards.len());
+        let mut last_counts = vec!(0; self.boards.len());
+        for _ in 0..trials {
+            let mut game = self.clone();
+            game.numbers.shuffle(&mut rng);
+            let simulation = game.simulate();
+            if let Some(placement) = simulation.finishing_order.first() {
+                first_counts[placement.board] += 1;
+            }
+            if simulation.never_won.is_empty() {
+                if let Some(placement) = simulation.finishing_order.last() {
+                    last_counts[placement.board] += 1;
+                }
             }
         }
-        0
+        Some((0..self.boards.len()).map(
+            |board| WinProbability {
+                board : board,
+                first : first_counts[board] as f64 / trials as f64,
+                last : last_counts[board] as f64 / trials as f64
+            }
+        ).collect())
     }
 
-    pub fn play_last(&mut self) -> u32 {
-        while let Some(number) = self.numbers.pop() {
-            let new_scores = self.mark_number(number);
-            if self.no_boards() {
-                return new_scores[0] * number;
+    // Inclusion-exclusion over the winning lines of each board, so the
+    // cost doubles with every line. Gives None if any board has more than
+    // MAX_EXACT_LINES lines, and None for a board that can't win. Draws are
+    // counted from one as in Placement.
+    pub fn expected_draws(&self) -> Option<Vec<Option<f64>>> {
+        let pool : BTreeSet<u32> = self.numbers.iter().cloned().collect();
+        let size = pool.len();
+        let board_sets : Vec<BTreeSet<BTreeSet<u32>>> = self.boards.iter().map(
+            |board| board.winning_sets(&self.rules, &pool).into_iter().collect()
+        ).collect();
+        if board_sets.iter().any(|winning_sets| winning_sets.len() > MAX_EXACT_LINES) {
+            return None;
+        }
+        Some(board_sets.into_iter().map(
+            |winning_sets| {
+                if winning_sets.is_empty() {
+                    return None;
+                }
+                let mut unions : HashMap<BTreeSet<u32>, i64> = HashMap::new();
+                unions.insert(BTreeSet::new(), 1);
+                for set in winning_sets.iter() {
+                    let extended : Vec<(BTreeSet<u32>, i64)> = unions.iter().map(
+                        |(union, sign)| (union.union(set).cloned().collect(), -sign)
+                    ).collect();
+                    for (union, sign) in extended {
+                        *unions.entry(union).or_insert(0) += sign;
+                    }
+                }
+                let expected = (0..size).map(
+                    |drawn| unions.iter().map(
+                        |(union, sign)| *sign as f64 * all_drawn_probability(union.len(), drawn, size)
+                    ).sum::<f64>()
+                ).sum();
+                Some(expected)
             }
+        ).collect())
+    }
+}
+
+fn all_drawn_probability(needed : usize, drawn : usize, size : usize) -> f64 {
+    if needed > drawn {
+        0.0
+    } else {
+        (0..needed).map(
+            |i| (drawn - i) as f64 / (size - i) as f64
+        ).product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Game {
+        let lines : Vec<String> = [
+            "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1",
+            "",
+            "22 13 17 11  0", " 8  2 23  4 24", "21  9 14 16  7", " 6 10  3 18  5", " 1 12 20 15 19",
+            "",
+            " 3 15  0  2 22", " 9 18 13 17  5", "19  8  7 25 23", "20 11 10 24  4", "14 21 16 12  6",
+            "",
+            "14 21 17 24  4", "10 16 15  9 19", "18  8 23 26 20", "22 11 13  6  5", " 2  0 12  3  7"
+        ].iter().map(|line| line.to_string()).collect();
+        Game::from_lines(lines).unwrap()
+    }
+
+    #[test]
+    fn example_scores_can_be_replayed() {
+        let game = example();
+        assert_eq!(game.play_first(), 4512);
+        assert_eq!(game.play_last(), 1924);
+        assert_eq!(game.play_first(), 4512);
+        let order = game.simulate().finishing_order;
+        assert_eq!(order[0], Placement { board : 2, draw : 12, number : 24, score : 4512 });
+    }
+
+    #[test]
+    fn analysis_uses_the_whole_pool() {
+        let game = example();
+        let before = game.expected_draws().unwrap();
+        game.play_last();
+        let after = game.expected_draws().unwrap();
+        for (first, second) in before.iter().zip(after.iter()) {
+            assert!((first.unwrap() - second.unwrap()).abs() < 1e-9);
         }
-        0
+        let chances = game.win_probabilities(100, 1).unwrap();
+        assert!((chances.iter().map(|chance| chance.first).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(game.win_probabilities(0, 1), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn large_boards_are_refused() {
+        let numbers = (0..100).map(|number| number.to_string()).collect::<Vec<String>>().join(",");
+        let mut lines = vec!(numbers, String::new());
+        lines.extend((0..10).map(
+            |row| (0..10).map(|column| (row * 10 + column).to_string()).collect::<Vec<String>>().join(" ")
+        ));
+        assert_eq!(Game::from_lines(lines).unwrap().expected_draws(), None);
+    }
+}
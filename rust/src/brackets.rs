@@ -1,109 +1,646 @@
-#[derive(PartialEq, Eq, Clone, Copy)]
-enum Bracket {
-    Paren,
-    Curly,
-    Angle,
-    Square
+use std::io::Read;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Bracket {
+    pub open : char,
+    pub close : char,
+    pub corrupt_score : usize,
+    pub completion_score : usize
+}
+
+#[derive(Clone, Debug)]
+pub struct Language {
+    brackets : Vec<Bracket>,
+    strict : bool
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Position {
+    pub line : usize,
+    pub column : usize
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Diagnostic {
+    Mismatched { position : Position, expected : char, found : char },
+    UnmatchedClose { position : Position, found : char },
+    Unclosed { position : Position, open : char },
+    UnknownCharacter { position : Position, found : char }
+}
+
+// Costs of each kind of edit, None if that kind isn't allowed
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct EditCosts {
+    pub insert : Option<usize>,
+    pub delete : Option<usize>,
+    pub substitute : Option<usize>
+}
+
+// Indices are character indices into the original line, an insert goes
+// before the character at its index.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Edit {
+    Insert { index : usize, char : char },
+    Delete { index : usize, char : char },
+    Substitute { index : usize, from : char, to : char }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repair {
+    pub line : String,
+    pub edits : Vec<Edit>,
+    pub cost : usize
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RepairStep {
+    Empty,
+    InsertPartner,
+    Delete,
+    Pair { partner : usize, bracket : usize }
+}
+
+// Bracket state as characters arrive, shared by whole strings and streams
+#[derive(Clone, Debug, Default)]
+struct Scanner {
+    stack : Vec<(Position, Bracket)>,
+    diagnostics : Vec<Diagnostic>,
+    corrupted : Option<(Position, Bracket)>
+}
+
+// Checks lines from chunks of input as they arrive, a chunk can end
+// anywhere including part way through a character.
+pub struct Validator<'a> {
+    language : &'a Language,
+    scanner : Scanner,
+    line : usize,
+    column : usize,
+    partial : Vec<u8>
+}
+
+#[derive(Clone, Debug)]
+pub struct LineReport {
+    pub line : usize,
+    pub parse : Parse
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub lines : usize,
+    pub corrupt_score : usize,
+    pub completion_scores : Vec<usize>
+}
+
+#[derive(Clone, Debug)]
+pub struct Parse {
+    pub diagnostics : Vec<Diagnostic>,
+    pub corrupted : Option<(Position, Bracket)>,
+    pub unclosed : Vec<(Position, Bracket)>
 }
 
 impl Bracket {
-    fn is_open(ch : char) -> Option<Bracket> {
-        match ch {
-            '(' => Some(Bracket::Paren),
-            '{' => Some(Bracket::Curly),
-            '<' => Some(Bracket::Angle),
-            '[' => Some(Bracket::Square),
-            _ => None
+    pub fn new(open : char, close : char, corrupt_score : usize, completion_score : usize) -> Bracket {
+        Bracket { open : open, close : close, corrupt_score : corrupt_score, completion_score : completion_score }
+    }
+}
+
+impl Default for EditCosts {
+    fn default() -> EditCosts {
+        EditCosts { insert : Some(1), delete : Some(1), substitute : Some(1) }
+    }
+}
+
+impl EditCosts {
+    // Only appending closing brackets, as when completing a line
+    pub fn insert_only() -> EditCosts {
+        EditCosts { insert : Some(1), delete : None, substitute : None }
+    }
+}
+
+impl Edit {
+    fn index(&self) -> usize {
+        match self {
+            Edit::Insert { index, .. } => *index,
+            Edit::Delete { index, .. } => *index,
+            Edit::Substitute { index, .. } => *index
+        }
+    }
+
+    fn is_insert(&self) -> bool {
+        matches!(self, Edit::Insert { .. })
+    }
+}
+
+impl Default for Language {
+    fn default() -> Language {
+        Language::new(vec!(
+            Bracket::new('(', ')', 3, 1),
+            Bracket::new('[', ']', 57, 2),
+            Bracket::new('{', '}', 1197, 3),
+            Bracket::new('<', '>', 25137, 4)
+        ))
+    }
+}
+
+impl Language {
+    pub fn new(brackets : Vec<Bracket>) -> Language {
+        Language { brackets : brackets, strict : false }
+    }
+
+    // Strict languages report any character that isn't a bracket
+    pub fn strict(mut self) -> Language {
+        self.strict = true;
+        self
+    }
+
+    fn is_open(&self, ch : char) -> Option<Bracket> {
+        self.brackets.iter().find(|bracket| bracket.open == ch).cloned()
+    }
+
+    fn is_closed(&self, ch : char) -> Option<Bracket> {
+        self.brackets.iter().find(|bracket| bracket.close == ch).cloned()
+    }
+
+    pub fn parse(&self, string : &str) -> Parse {
+        let mut scanner = Scanner::default();
+        for (line_index, line) in string.split('\n').enumerate() {
+            for (column_index, char) in line.chars().enumerate() {
+                scanner.push(self, Position { line : line_index + 1, column : column_index + 1 }, char);
+            }
         }
+        scanner.finish()
     }
-    fn is_closed(ch : char) -> Option<Bracket> {
-        match ch {
-            ')' => Some(Bracket::Paren),
-            '}' => Some(Bracket::Curly),
-            '>' => Some(Bracket::Angle),
-            ']' => Some(Bracket::Square),
-            _ => None
+
+    pub fn validator(&self) -> Validator<'_> {
+        Validator {
+            language : self,
+            scanner : Scanner::default(),
+            line : 1,
+            column : 0,
+            partial : Vec::new()
         }
     }
-    fn corrupt_score(&self) -> usize {
-        match self {
-            Bracket::Paren => 3,
-            Bracket::Square => 57,
-            Bracket::Curly => 1197,
-            Bracket::Angle => 25137
+
+    // Reads the input a chunk at a time, handing each line's report to the
+    // callback once the line ends.
+    pub fn validate<R, F>(&self, mut reader : R, chunk_size : usize, mut on_line : F) -> std::io::Result<Summary>
+        where R : Read, F : FnMut(&LineReport)
+    {
+        let mut validator = self.validator();
+        let mut summary = Summary::default();
+        let mut buffer = vec!(0; std::cmp::max(chunk_size, 1));
+        loop {
+            let read = reader.read(&mut buffer)?;
+            let reports = if read == 0 {
+                validator.finish().into_iter().collect()
+            } else {
+                validator.feed(&buffer[..read])
+            };
+            for report in reports.iter() {
+                summary.add(report);
+                on_line(report);
+            }
+            if read == 0 {
+                return Ok(summary);
+            }
         }
     }
-    fn completetion_score(&self) -> usize {
-        match self {
-            Bracket::Paren => 1,
-            Bracket::Square => 2,
-            Bracket::Curly => 3,
-            Bracket::Angle => 4
+
+    fn bracket_index(&self, ch : char) -> Option<(usize, bool)> {
+        self.brackets.iter().position(|bracket| bracket.open == ch).map(
+            |index| (index, true)
+        ).or_else(
+            || self.brackets.iter().position(|bracket| bracket.close == ch).map(|index| (index, false))
+        )
+    }
+
+    fn substitution(&self, costs : &EditCosts, from : char, to : char) -> Option<usize> {
+        if from == to { Some(0) } else { costs.substitute }
+    }
+
+    // Interval dynamic programming over the brackets of the line, the
+    // cheapest way to balance a range either fixes up its first bracket
+    // with a partner inside the range, gives it an inserted partner or
+    // deletes it. Other characters are left where they are.
+    pub fn repair(&self, line : &str, costs : &EditCosts) -> Option<Repair> {
+        let chars : Vec<char> = line.chars().collect();
+        let brackets : Vec<(usize, usize, bool)> = chars.iter().enumerate().filter_map(
+            |(index, ch)| self.bracket_index(*ch).map(|(bracket, open)| (index, bracket, open))
+        ).collect();
+        let size = brackets.len();
+        let mut best : Vec<Vec<Option<(usize, RepairStep)>>> = vec!(vec!(None; size + 1); size + 1);
+        for start in 0..(size+1) {
+            best[start][start] = Some((0, RepairStep::Empty));
+        }
+        for length in 1..(size+1) {
+            for start in 0..(size + 1 - length) {
+                let end = start + length;
+                let mut options : Vec<(usize, RepairStep)> = Vec::new();
+                if let (Some(insert), Some((rest, _))) = (costs.insert, best[start+1][end]) {
+                    options.push((insert + rest, RepairStep::InsertPartner));
+                }
+                if let (Some(delete), Some((rest, _))) = (costs.delete, best[start+1][end]) {
+                    options.push((delete + rest, RepairStep::Delete));
+                }
+                let first = chars[brackets[start].0];
+                for partner in (start+1)..end {
+                    let last = chars[brackets[partner].0];
+                    let (inner, outer) = match (best[start+1][partner], best[partner+1][end]) {
+                        (Some((inner, _)), Some((outer, _))) => (inner, outer),
+                        _ => continue
+                    };
+                    for (index, bracket) in self.brackets.iter().enumerate() {
+                        if let (Some(open), Some(close)) = (
+                            self.substitution(costs, first, bracket.open),
+                            self.substitution(costs, last, bracket.close)
+                        ) {
+                            options.push((open + close + inner + outer, RepairStep::Pair { partner : partner, bracket : index }));
+                        }
+                    }
+                }
+                // Ties keep the earliest option so complete lines get their
+                // closing brackets appended rather than rearranged.
+                for option in options {
+                    if best[start][end].map(|(cost, _)| option.0 < cost).unwrap_or(true) {
+                        best[start][end] = Some(option);
+                    }
+                }
+            }
+        }
+        let (cost, _) = best[0][size]?;
+        let mut edits : Vec<Edit> = Vec::new();
+        // Walk the choices depth first keeping edits in left to right order,
+        // closing edits wait until their inner range is finished.
+        let mut stack : Vec<Option<(usize, usize)>> = vec!(Some((0, size)));
+        let mut closing : Vec<Option<Edit>> = Vec::new();
+        while let Some(entry) = stack.pop() {
+            let (start, end) = match entry {
+                Some(range) => range,
+                None => {
+                    if let Some(Some(edit)) = closing.pop() {
+                        edits.push(edit);
+                    }
+                    continue;
+                }
+            };
+            let (index, bracket, open) = match brackets.get(start) {
+                Some(entry) if start < end => *entry,
+                _ => continue
+            };
+            let end_index = brackets.get(end).map(|entry| entry.0).unwrap_or(chars.len());
+            match best[start][end].unwrap().1 {
+                RepairStep::Empty => (),
+                RepairStep::InsertPartner => {
+                    if open {
+                        closing.push(Some(Edit::Insert { index : end_index, char : self.brackets[bracket].close }));
+                        stack.push(None);
+                    } else {
+                        edits.push(Edit::Insert { index : index, char : self.brackets[bracket].open });
+                    }
+                    stack.push(Some((start + 1, end)));
+                },
+                RepairStep::Delete => {
+                    edits.push(Edit::Delete { index : index, char : chars[index] });
+                    stack.push(Some((start + 1, end)));
+                },
+                RepairStep::Pair { partner, bracket : pair } => {
+                    let pair = self.brackets[pair];
+                    if chars[index] != pair.open {
+                        edits.push(Edit::Substitute { index : index, from : chars[index], to : pair.open });
+                    }
+                    let partner_index = brackets[partner].0;
+                    closing.push(if chars[partner_index] != pair.close {
+                        Some(Edit::Substitute { index : partner_index, from : chars[partner_index], to : pair.close })
+                    } else {
+                        None
+                    });
+                    stack.push(Some((partner + 1, end)));
+                    stack.push(None);
+                    stack.push(Some((start + 1, partner)));
+                }
+            }
         }
+        edits.sort_by_key(|edit| (edit.index(), !edit.is_insert()));
+        let mut repaired = String::new();
+        let mut edit_iter = edits.iter().peekable();
+        for index in 0..(chars.len()+1) {
+            let mut keep = index < chars.len();
+            let mut replacement = chars.get(index).cloned();
+            while let Some(edit) = edit_iter.next_if(|edit| edit.index() == index) {
+                match edit {
+                    Edit::Insert { char, .. } => repaired.push(*char),
+                    Edit::Delete { .. } => keep = false,
+                    Edit::Substitute { to, .. } => replacement = Some(*to)
+                }
+            }
+            if keep {
+                repaired.extend(replacement);
+            }
+        }
+        Some(Repair { line : repaired, edits : edits, cost : cost })
+    }
+
+    pub fn parse_score(&self, lines : &Vec<String>) -> usize {
+        lines.iter().filter_map(
+            |line| self.parse(line).corrupt().map(
+                |bracket| bracket.corrupt_score
+            )
+        ).sum()
+    }
+
+    // Completing an incomplete line is its cheapest repair by inserts
+    pub fn completion_score(&self, repair : &Repair) -> usize {
+        repair.edits.iter().fold(0, |score, edit| match edit {
+            Edit::Insert { char, .. } => score * 5 + self.is_closed(*char).map(
+                |bracket| bracket.completion_score
+            ).unwrap_or(0),
+            _ => score
+        })
+    }
+
+    pub fn parse_complete_score(&self, lines : &Vec<String>) -> Option<usize> {
+        let scores : Vec<usize> = lines.iter().filter_map(
+            |line| self.parse(line).completion_score()
+        ).collect();
+        median(scores)
     }
 }
 
-enum ParseResult {
-    Corrupt(Bracket),
-    Incomplete(Vec<Bracket>)
+fn median(mut scores : Vec<usize>) -> Option<usize> {
+    scores.sort();
+    scores.get(scores.len() / 2).cloned()
 }
 
-impl ParseResult {
-    fn corrupt(&self) -> Option<Bracket> {
-        match self {
-            ParseResult::Corrupt(bracket) => Some(*bracket),
-            _ => None
+impl Scanner {
+    // Keeps going after a mismatch by treating the close as closing the
+    // innermost open bracket anyway, so every problem is reported.
+    fn push(&mut self, language : &Language, position : Position, char : char) {
+        if let Some(bracket) = language.is_open(char) {
+            self.stack.push((position, bracket));
+        } else if let Some(bracket) = language.is_closed(char) {
+            let top = self.stack.pop();
+            if self.corrupted.is_none() && top.map(|(_, expected)| expected != bracket).unwrap_or(true) {
+                self.corrupted = Some((position, bracket));
+            }
+            match top {
+                Some((_, expected)) if expected != bracket => self.diagnostics.push(Diagnostic::Mismatched {
+                    position : position, expected : expected.close, found : char
+                }),
+                Some(_) => (),
+                None => self.diagnostics.push(Diagnostic::UnmatchedClose { position : position, found : char })
+            }
+        } else if language.strict && char != '\r' {
+            self.diagnostics.push(Diagnostic::UnknownCharacter { position : position, found : char });
         }
     }
 
-    fn incomplete(&self) -> Option<Vec<Bracket>> {
-        match self {
-            ParseResult::Incomplete(brackets) => Some(brackets.clone()),
-            _ => None
-        }
+    fn finish(mut self) -> Parse {
+        self.diagnostics.extend(self.stack.iter().map(
+            |(position, bracket)| Diagnostic::Unclosed { position : *position, open : bracket.open }
+        ));
+        Parse { diagnostics : self.diagnostics, corrupted : self.corrupted, unclosed : self.stack }
     }
 }
 
-fn attempt_parse(string : &str) -> ParseResult {
-    let mut stack : Vec<Bracket> = Vec::new();
-    for char in string.chars() {
-        if let Some(bracket) = Bracket::is_open(char) {
-            stack.push(bracket);
-        } 
-        if let Some(bracket) = Bracket::is_closed(char) {
-            if let Some(expected) = stack.pop() {
-                if expected != bracket {
-                    return ParseResult::Corrupt(bracket);
+impl<'a> Validator<'a> {
+    pub fn feed(&mut self, chunk : &[u8]) -> Vec<LineReport> {
+        self.partial.extend_from_slice(chunk);
+        let mut text = String::new();
+        loop {
+            match std::str::from_utf8(&self.partial) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    self.partial.clear();
+                    break;
+                },
+                Err(error) => {
+                    let valid = error.valid_up_to();
+                    text.push_str(std::str::from_utf8(&self.partial[..valid]).unwrap());
+                    match error.error_len() {
+                        // Invalid bytes rather than a character cut off by the chunk
+                        Some(invalid) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            self.partial.drain(..(valid + invalid));
+                        },
+                        None => {
+                            self.partial.drain(..valid);
+                            break;
+                        }
+                    }
                 }
             }
-        } 
+        }
+        let mut reports = Vec::new();
+        for char in text.chars() {
+            if char == '\n' {
+                reports.push(self.end_line());
+            } else {
+                self.column += 1;
+                let position = Position { line : self.line, column : self.column };
+                self.scanner.push(self.language, position, char);
+            }
+        }
+        reports
+    }
+
+    // A last line without a newline only counts if it has something in it
+    pub fn finish(&mut self) -> Option<LineReport> {
+        // Left over bytes are a cut off character so can't hold a newline
+        let rest : Vec<u8> = self.partial.drain(..).collect();
+        self.feed(String::from_utf8_lossy(&rest).as_bytes());
+        if self.column > 0 {
+            Some(self.end_line())
+        } else {
+            None
+        }
+    }
+
+    fn end_line(&mut self) -> LineReport {
+        let scanner = std::mem::take(&mut self.scanner);
+        let report = LineReport { line : self.line, parse : scanner.finish() };
+        self.line += 1;
+        self.column = 0;
+        report
     }
-    ParseResult::Incomplete(stack)
 }
 
-fn completetion_score(brackets : &Vec<Bracket>) -> usize {
-    let mut score : usize = 0;
-    for bracket in brackets.iter().rev() {
-        score *= 5;
-        score += bracket.completetion_score();
+impl Summary {
+    fn add(&mut self, report : &LineReport) {
+        self.lines += 1;
+        if let Some(bracket) = report.parse.corrupt() {
+            self.corrupt_score += bracket.corrupt_score;
+        } else if let Some(completion) = report.parse.completion_score() {
+            self.completion_scores.push(completion);
+        }
+    }
+
+    pub fn median_completion_score(&self) -> Option<usize> {
+        median(self.completion_scores.clone())
     }
-    score
 }
 
-pub fn parse_score(lines : &Vec<String>) -> usize {
-    lines.iter().filter_map(
-        |line| attempt_parse(line).corrupt().map(
-            |bracket| bracket.corrupt_score()
+impl Parse {
+    // The first close bracket that doesn't fit
+    pub fn corrupt(&self) -> Option<Bracket> {
+        self.corrupted.map(|(_, bracket)| bracket)
+    }
+
+    // Balanced lines are neither corrupt nor incomplete
+    pub fn incomplete(&self) -> Option<Vec<Bracket>> {
+        if self.corrupted.is_some() || self.unclosed.is_empty() {
+            None
+        } else {
+            Some(self.unclosed.iter().map(|(_, bracket)| *bracket).collect())
+        }
+    }
+
+    // Scored from the innermost bracket still open, the same as scoring the
+    // closing brackets a repair by inserts appends.
+    pub fn completion_score(&self) -> Option<usize> {
+        self.incomplete().map(
+            |brackets| brackets.iter().rev().fold(0, |score, bracket| score * 5 + bracket.completion_score)
         )
-    ).sum()
+    }
+}
+
+pub fn parse_score(lines : &Vec<String>) -> usize {
+    Language::default().parse_score(lines)
 }
 
 pub fn parse_complete_score(lines : &Vec<String>) -> usize {
-    let mut scores : Vec<usize> = lines.iter().filter_map(
-        |line| attempt_parse(line).incomplete().map(
-            |brackets| completetion_score(&brackets)
-        )
-    ).collect();
-    scores.sort();
-    scores[scores.len() / 2]
-}
\ No newline at end of file
+    Language::default().parse_complete_score(lines).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every balanced line of the given number of pairs of round and square
+    // brackets, which is enough for lines only using those.
+    fn balanced(pairs : usize) -> Vec<String> {
+        if pairs == 0 {
+            return vec!(String::new());
+        }
+        let mut lines = Vec::new();
+        for inner in 0..pairs {
+            for (open, close) in [('(', ')'), ('[', ']')] {
+                for middle in balanced(inner) {
+                    for rest in balanced(pairs - 1 - inner) {
+                        lines.push(format!("{}{}{}{}", open, middle, close, rest));
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    fn edit_distance(from : &[char], to : &[char], costs : &EditCosts) -> Option<usize> {
+        let mut best : Vec<Vec<Option<usize>>> = vec!(vec!(None; to.len() + 1); from.len() + 1);
+        best[0][0] = Some(0);
+        for i in 0..(from.len()+1) {
+            for j in 0..(to.len()+1) {
+                let options = [
+                    if i > 0 { best[i-1][j].zip(costs.delete).map(|(a, b)| a + b) } else { None },
+                    if j > 0 { best[i][j-1].zip(costs.insert).map(|(a, b)| a + b) } else { None },
+                    if i > 0 && j > 0 {
+                        let change = if from[i-1] == to[j-1] { Some(0) } else { costs.substitute };
+                        best[i-1][j-1].zip(change).map(|(a, b)| a + b)
+                    } else {
+                        None
+                    }
+                ];
+                for option in options.into_iter().flatten() {
+                    if best[i][j].map(|cost| option < cost).unwrap_or(true) {
+                        best[i][j] = Some(option);
+                    }
+                }
+            }
+        }
+        best[from.len()][to.len()]
+    }
+
+    fn apply(line : &str, edits : &[Edit]) -> String {
+        let chars : Vec<char> = line.chars().collect();
+        let mut edited = String::new();
+        for index in 0..(chars.len()+1) {
+            let mut current = chars.get(index).cloned();
+            for edit in edits.iter() {
+                match *edit {
+                    Edit::Insert { index : at, char } if at == index => edited.push(char),
+                    Edit::Delete { index : at, char } if at == index => {
+                        assert_eq!(current, Some(char));
+                        current = None;
+                    },
+                    Edit::Substitute { index : at, from, to } if at == index => {
+                        assert_eq!(current, Some(from));
+                        current = Some(to);
+                    },
+                    _ => ()
+                }
+            }
+            edited.extend(current);
+        }
+        edited
+    }
+
+    fn lines(length : usize) -> Vec<String> {
+        if length == 0 {
+            return vec!(String::new());
+        }
+        lines(length - 1).into_iter().flat_map(
+            |line| "()[]".chars().map(move |c| format!("{}{}", line, c))
+        ).collect()
+    }
+
+    #[test]
+    fn repairs_are_minimal() {
+        let language = Language::default();
+        let targets : Vec<Vec<char>> = (0..5).flat_map(balanced).map(|line| line.chars().collect()).collect();
+        let all_costs = [
+            EditCosts::default(),
+            EditCosts::insert_only(),
+            EditCosts { insert : Some(2), delete : Some(3), substitute : Some(1) },
+            EditCosts { insert : Some(1), delete : None, substitute : Some(3) }
+        ];
+        for costs in all_costs.iter() {
+            for line in (0..5).flat_map(lines) {
+                let chars : Vec<char> = line.chars().collect();
+                let cheapest = targets.iter().filter_map(|target| edit_distance(&chars, target, costs)).min();
+                let repair = language.repair(&line, costs);
+                assert_eq!(repair.as_ref().map(|repair| repair.cost), cheapest, "{} {:?}", line, costs);
+                if let Some(repair) = repair {
+                    let repaired : Vec<char> = repair.line.chars().collect();
+                    assert!(targets.contains(&repaired), "{} {:?}", line, repair);
+                    assert_eq!(apply(&line, &repair.edits), repair.line);
+                    let edit_costs : Option<usize> = repair.edits.iter().map(
+                        |edit| match edit {
+                            Edit::Insert { .. } => costs.insert,
+                            Edit::Delete { .. } => costs.delete,
+                            Edit::Substitute { .. } => costs.substitute
+                        }
+                    ).sum();
+                    assert_eq!(edit_costs, Some(repair.cost), "{} {:?}", line, repair);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_and_batch_scores_agree() {
+        let lines : Vec<String> = [
+            "[({(<(())[]>[[{[]{<()<>>", "[(()[<>])]({[<{<<[]>>(", "{([(<{}[<>[]}>{[]{[(<()>",
+            "(((({<>}<{<{<>}{[]{[]{}", "[[<[([]))<([[{}[[()]]]", "[{[{({}]{}}([{[{{{}}([]",
+            "{<[[]]>}<{[{[{[]{()[[[]", "[<(<(<(<{}))><([]([]()", "<{([([[(<>()){}]>(<<{{",
+            "<{([{{}}[<[[[<>{}]]]>[]]", "()", ""
+        ].iter().map(|line| line.to_string()).collect();
+        let language = Language::default();
+        let input = lines.join("\n");
+        for chunk_size in [1, 3, 7, 1000] {
+            let summary = language.validate(input.as_bytes(), chunk_size, |_| ()).unwrap();
+            assert_eq!(summary.corrupt_score, language.parse_score(&lines));
+            assert_eq!(summary.median_completion_score(), language.parse_complete_score(&lines));
+        }
+        assert_eq!(language.parse_score(&lines), 26397);
+        assert_eq!(language.parse_complete_score(&lines), Some(288957));
+    }
+}
@@ -21,11 +21,82 @@ impl Cavern {
         cave
     }
 }
+// The small caves seen so far on the way to a cave, small caves are
+// numbered to give each a bit in the mask.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Visited {
+    cave : Cavern,
+    mask : u64,
+    remaining : usize,
+    repeats : Vec<u8>
+}
+
+#[derive(Default)]
+struct LowLinks {
+    discovered : HashMap<Cavern, usize>,
+    low : HashMap<Cavern, usize>,
+    bridges : Vec<(Cavern, Cavern)>,
+    articulation_points : HashSet<Cavern>
+}
+
+struct PathCounter {
+    start : Cavern,
+    end : Cavern,
+    revisits : Revisits,
+    bits : HashMap<Cavern, usize>,
+    memo : HashMap<Visited, usize>
+}
+
 pub struct CaveSystem {
     nodemap : HashMap<String, Cavern>,
+    names : Vec<String>,
     graph : UnGraphMap<Cavern, ()>
 }
 
+// How often small caves other than start and end can be entered again,
+// either a number of extra visits shared by all of them or a most times
+// each one can be visited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Revisits {
+    Total(usize),
+    PerCave(usize)
+}
+
+// Length is the number of moves between caves
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    pub revisits : Revisits,
+    pub must_visit : Vec<String>,
+    pub avoid : Vec<String>,
+    pub max_length : Option<usize>
+}
+
+impl Default for Revisits {
+    fn default() -> Revisits {
+        Revisits::Total(0)
+    }
+}
+
+struct Frame {
+    cave : Cavern,
+    neighbours : Vec<Cavern>,
+    next : usize,
+    revisit : bool
+}
+
+pub struct Paths<'a> {
+    system : &'a CaveSystem,
+    end : Cavern,
+    start : Cavern,
+    must_visit : Vec<Cavern>,
+    avoid : HashSet<Cavern>,
+    max_length : Option<usize>,
+    revisits : Revisits,
+    revisits_left : usize,
+    visits : HashMap<Cavern, usize>,
+    stack : Vec<Frame>
+}
+
 impl CaveSystem {
     pub fn from_lines(lines : Vec<String>) -> CaveSystem {
         let mut nodemap : HashMap<String, Cavern> = HashMap::new();
@@ -41,75 +112,440 @@ impl CaveSystem {
                 }
             }
         ).collect();
+        let mut names : Vec<String> = vec!(String::new(); nodemap.len());
+        for (name, cave) in nodemap.iter() {
+            names[cave.id as usize] = name.clone();
+        }
         CaveSystem {
             nodemap : nodemap,
+            names : names,
             graph : UnGraphMap::from_edges(
                 edges.into_iter()
             )
         }
     }
 
+    fn name(&self, cave : &Cavern) -> &str {
+        &self.names[cave.id as usize]
+    }
+
+    // None if the system has no start or end, a filter names an unknown
+    // cave, or joined large caves would make the paths go on forever.
+    pub fn paths(&self, filter : &PathFilter) -> Option<Paths<'_>> {
+        let start = *self.nodemap.get("start")?;
+        let end = *self.nodemap.get("end")?;
+        if filter.max_length.is_none() && self.has_adjacent_large_caves() {
+            return None;
+        }
+        let must_visit = filter.must_visit.iter().map(
+            |name| self.nodemap.get(name).cloned()
+        ).collect::<Option<Vec<Cavern>>>()?;
+        let avoid = filter.avoid.iter().map(
+            |name| self.nodemap.get(name).cloned()
+        ).collect::<Option<HashSet<Cavern>>>()?;
+        let mut paths = Paths {
+            system : self,
+            end : end,
+            start : start,
+            must_visit : must_visit,
+            avoid : avoid,
+            max_length : filter.max_length,
+            revisits : filter.revisits,
+            revisits_left : match filter.revisits {
+                Revisits::Total(revisits) => revisits,
+                Revisits::PerCave(_) => 0
+            },
+            visits : HashMap::new(),
+            stack : Vec::new()
+        };
+        if !paths.avoid.contains(&start) {
+            paths.enter(start, false);
+        }
+        Some(paths)
+    }
+
     pub fn number_paths(&self) -> usize {
-        let mut restrictions : HashSet<Cavern> = HashSet::new();
-        let start = *self.nodemap.get("start").unwrap();
-        let end = *self.nodemap.get("end").unwrap();
-        self.number_paths_restricted(start, end, &mut restrictions)
+        self.count_paths(Revisits::Total(0)).unwrap_or(0)
     }
 
     pub fn number_paths_single_reentry(&self) -> usize {
-        let mut restrictions : HashSet<Cavern> = HashSet::new();
-        let start = *self.nodemap.get("start").unwrap();
-        let end = *self.nodemap.get("end").unwrap();
-        self.number_paths_restricted_reentry(start, end, &mut restrictions, start)
+        self.count_paths(Revisits::Total(1)).unwrap_or(0)
     }
 
-    fn number_paths_restricted(&self, from : Cavern, to : Cavern, restrictions : &mut HashSet<Cavern>) -> usize {
-        if from == to {
-            return 1;
+    // Memoised on the current cave and which small caves have been seen.
+    // None if there is no start or end, there are too many small caves for
+    // the mask, or two large caves are joined so there are infinitely many
+    // paths.
+    pub fn count_paths(&self, revisits : Revisits) -> Option<usize> {
+        let start = *self.nodemap.get("start")?;
+        let end = *self.nodemap.get("end")?;
+        if self.has_adjacent_large_caves() {
+            return None;
         }
-        let mut sum = 0;
-        for neighbour in self.graph.neighbors(from) {
-            if !restrictions.contains(&neighbour) {
-                if !from.large {
-                    restrictions.insert(from);
-                    sum += self.number_paths_restricted(neighbour, to, restrictions);
-                    restrictions.remove(&from);
-                } else {
-                    sum += self.number_paths_restricted(neighbour, to, restrictions);
+        let mut small : Vec<Cavern> = self.graph.nodes().filter(|cave| !cave.large).collect();
+        small.sort();
+        if small.len() > 64 {
+            return None;
+        }
+        let bits : HashMap<Cavern, usize> = small.iter().enumerate().map(
+            |(bit, cave)| (*cave, bit)
+        ).collect();
+        let (remaining, repeats) = match revisits {
+            Revisits::Total(revisits) => (revisits, Vec::new()),
+            Revisits::PerCave(most) if most > 1 => (0, vec!(0; small.len())),
+            Revisits::PerCave(_) => (0, Vec::new())
+        };
+        let initial = Visited {
+            cave : start,
+            mask : if start.large { 0 } else { 1 << bits[&start] },
+            remaining : remaining,
+            repeats : repeats
+        };
+        let mut counter = PathCounter {
+            start : start,
+            end : end,
+            revisits : revisits,
+            bits : bits,
+            memo : HashMap::new()
+        };
+        Some(counter.count_from(&self.graph, &initial))
+    }
+
+    fn has_adjacent_large_caves(&self) -> bool {
+        self.graph.all_edges().any(|(cave1, cave2, _)| cave1.large && cave2.large)
+    }
+
+    fn named_edge(&self, cave1 : Cavern, cave2 : Cavern) -> (String, String) {
+        let (name1, name2) = (self.name(&cave1).to_string(), self.name(&cave2).to_string());
+        if name1 <= name2 { (name1, name2) } else { (name2, name1) }
+    }
+
+    fn sorted_names<I>(&self, caves : I) -> Vec<String>
+        where I : Iterator<Item = Cavern>
+    {
+        let mut names : Vec<String> = caves.map(|cave| self.name(&cave).to_string()).collect();
+        names.sort();
+        names
+    }
+
+    // Large caves are drawn as filled boxes and small caves as ellipses
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph caves {\n");
+        for name in self.sorted_names(self.graph.nodes()) {
+            let style = if self.nodemap[&name].large { "shape=box, style=filled" } else { "shape=ellipse" };
+            dot.push_str(&format!("    {} [label={}, {}];\n", dot_string(&name), dot_string(&name), style));
+        }
+        let mut edges : Vec<(String, String)> = self.graph.all_edges().map(
+            |(cave1, cave2, _)| self.named_edge(cave1, cave2)
+        ).collect();
+        edges.sort();
+        for (name1, name2) in edges {
+            dot.push_str(&format!("    {} -- {};\n", dot_string(&name1), dot_string(&name2)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn adjacent_large_caves(&self) -> Vec<(String, String)> {
+        let mut edges : Vec<(String, String)> = self.graph.all_edges().filter(
+            |(cave1, cave2, _)| cave1.large && cave2.large
+        ).map(
+            |(cave1, cave2, _)| self.named_edge(cave1, cave2)
+        ).collect();
+        edges.sort();
+        edges
+    }
+
+    // Caves in a different connected part of the system to the named cave
+    pub fn unreachable_from(&self, name : &str) -> Option<Vec<String>> {
+        let from = *self.nodemap.get(name)?;
+        let mut seen : HashSet<Cavern> = HashSet::new();
+        let mut to_visit : Vec<Cavern> = vec!(from);
+        while let Some(cave) = to_visit.pop() {
+            if seen.insert(cave) {
+                to_visit.extend(self.graph.neighbors(cave));
+            }
+        }
+        Some(self.sorted_names(self.graph.nodes().filter(|cave| !seen.contains(cave))))
+    }
+
+    pub fn bridges(&self) -> Vec<(String, String)> {
+        let mut bridges : Vec<(String, String)> = self.low_links().bridges.into_iter().map(
+            |(cave1, cave2)| self.named_edge(cave1, cave2)
+        ).collect();
+        bridges.sort();
+        bridges
+    }
+
+    pub fn articulation_points(&self) -> Vec<String> {
+        self.sorted_names(self.low_links().articulation_points.into_iter())
+    }
+
+    // Tarjan's depth first search, an edge is a bridge if nothing below it
+    // reaches back above it and a cave is an articulation point if some
+    // child's subtree can't get back above the cave.
+    fn low_links(&self) -> LowLinks {
+        let mut low_links = LowLinks::default();
+        let mut roots : Vec<Cavern> = self.graph.nodes().collect();
+        roots.sort();
+        for root in roots {
+            if !low_links.discovered.contains_key(&root) {
+                let children = self.low_link_search(root, None, &mut low_links);
+                if children > 1 {
+                    low_links.articulation_points.insert(root);
                 }
             }
         }
-        sum
+        low_links
+    }
+
+    fn low_link_search(&self, cave : Cavern, parent : Option<Cavern>, low_links : &mut LowLinks) -> usize {
+        let discovered = low_links.discovered.len();
+        low_links.discovered.insert(cave, discovered);
+        low_links.low.insert(cave, discovered);
+        let mut children = 0;
+        for neighbour in self.graph.neighbors(cave) {
+            if Some(neighbour) == parent {
+                continue;
+            }
+            if let Some(neighbour_discovered) = low_links.discovered.get(&neighbour).cloned() {
+                let low = std::cmp::min(low_links.low[&cave], neighbour_discovered);
+                low_links.low.insert(cave, low);
+            } else {
+                children += 1;
+                self.low_link_search(neighbour, Some(cave), low_links);
+                let neighbour_low = low_links.low[&neighbour];
+                let low = std::cmp::min(low_links.low[&cave], neighbour_low);
+                low_links.low.insert(cave, low);
+                if neighbour_low > discovered {
+                    low_links.bridges.push((cave, neighbour));
+                }
+                if parent.is_some() && neighbour_low >= discovered {
+                    low_links.articulation_points.insert(cave);
+                }
+            }
+        }
+        children
     }
+}
 
-    fn number_paths_restricted_reentry(&self, from : Cavern, to : Cavern, restrictions : &mut HashSet<Cavern>, start : Cavern) -> usize {
-        if from == to {
+impl PathCounter {
+    fn count_from(&mut self, graph : &UnGraphMap<Cavern, ()>, visited : &Visited) -> usize {
+        if visited.cave == self.end {
             return 1;
         }
+        if let Some(count) = self.memo.get(visited) {
+            return *count;
+        }
         let mut sum = 0;
-        for neighbour in self.graph.neighbors(from) {
-            if !restrictions.contains(&neighbour) {
-                if !from.large {
-                    restrictions.insert(from);
-                    sum += self.number_paths_restricted_reentry(neighbour, to, restrictions, start);
-                    restrictions.remove(&from);
+        for neighbour in graph.neighbors(visited.cave) {
+            if neighbour == self.start {
+                continue;
+            }
+            let mut next = visited.clone();
+            next.cave = neighbour;
+            if !neighbour.large {
+                let bit = self.bits[&neighbour];
+                if next.mask & (1 << bit) == 0 {
+                    next.mask |= 1 << bit;
+                } else if neighbour == self.end {
+                    continue;
                 } else {
-                    sum += self.number_paths_restricted_reentry(neighbour, to, restrictions, start);
-                }
-            } else {
-                if neighbour != start {
-                    restrictions.remove(&neighbour);
-                    if !from.large {
-                        restrictions.insert(from);
-                        sum += self.number_paths_restricted(neighbour, to, restrictions);
-                        restrictions.remove(&from);
-                    } else {
-                        sum += self.number_paths_restricted(neighbour, to, restrictions);
+                    match self.revisits {
+                        Revisits::Total(_) if next.remaining > 0 => next.remaining -= 1,
+                        Revisits::PerCave(most) if most > 1 && next.repeats[bit] as usize + 1 < most => next.repeats[bit] += 1,
+                        _ => continue
                     }
-                    restrictions.insert(neighbour);
                 }
             }
+            sum += self.count_from(graph, &next);
         }
+        self.memo.insert(visited.clone(), sum);
         sum
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Paths<'a> {
+    fn enter(&mut self, cave : Cavern, revisit : bool) {
+        *self.visits.entry(cave).or_insert(0) += 1;
+        if revisit {
+            self.revisits_left -= 1;
+        }
+        let neighbours = if cave == self.end {
+            Vec::new()
+        } else {
+            self.system.graph.neighbors(cave).collect()
+        };
+        self.stack.push(Frame { cave : cave, neighbours : neighbours, next : 0, revisit : revisit });
+    }
+
+    fn leave(&mut self) {
+        if let Some(frame) = self.stack.pop() {
+            *self.visits.get_mut(&frame.cave).unwrap() -= 1;
+            if frame.revisit {
+                self.revisits_left += 1;
+            }
+        }
+    }
+
+    // Whether the cave can be entered next and if so whether it is a revisit
+    fn allowed(&self, cave : Cavern) -> Option<bool> {
+        if self.avoid.contains(&cave) {
+            return None;
+        }
+        let visits = self.visits.get(&cave).cloned().unwrap_or(0);
+        if cave.large || visits == 0 {
+            Some(false)
+        } else if cave == self.start || cave == self.end {
+            None
+        } else {
+            match self.revisits {
+                Revisits::Total(_) if self.revisits_left > 0 => Some(true),
+                Revisits::PerCave(most) if visits < most => Some(false),
+                _ => None
+            }
+        }
+    }
+
+    fn current_path(&self) -> Vec<String> {
+        self.stack.iter().map(
+            |frame| self.system.name(&frame.cave).to_string()
+        ).collect()
+    }
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Vec<String>> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            if frame.cave == self.end && frame.next == 0 {
+                frame.next = 1;
+                let complete = self.must_visit.iter().all(
+                    |cave| self.visits.get(cave).cloned().unwrap_or(0) > 0
+                );
+                if complete {
+                    return Some(self.current_path());
+                }
+                continue;
+            }
+            let moves = self.stack.len() - 1;
+            let frame = self.stack.last_mut().unwrap();
+            let at_limit = self.max_length.map(|max| moves >= max).unwrap_or(false);
+            if at_limit || frame.next >= frame.neighbours.len() {
+                self.leave();
+                continue;
+            }
+            let neighbour = frame.neighbours[frame.next];
+            frame.next += 1;
+            if let Some(revisit) = self.allowed(neighbour) {
+                self.enter(neighbour, revisit);
+            }
+        }
+    }
+}
+
+fn json_string(string : &str) -> String {
+    let mut quoted = String::from("\"");
+    for char in string.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Quoted DOT ID, where a backslash starts an escape in labels
+fn dot_string(string : &str) -> String {
+    let mut quoted = String::from("\"");
+    for char in string.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// One path per line with caves separated by commas
+pub fn paths_to_text(paths : &[Vec<String>]) -> String {
+    paths.iter().map(
+        |path| path.join(",") + "\n"
+    ).collect()
+}
+
+pub fn paths_to_json(paths : &[Vec<String>]) -> String {
+    let rows : Vec<String> = paths.iter().map(
+        |path| format!("[{}]", path.iter().map(|name| json_string(name)).collect::<Vec<String>>().join(","))
+    ).collect();
+    format!("[{}]", rows.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn system(edges : &[&str]) -> CaveSystem {
+        CaveSystem::from_lines(edges.iter().map(|edge| edge.to_string()).collect())
+    }
+
+    fn filter(revisits : Revisits) -> PathFilter {
+        PathFilter { revisits : revisits, ..PathFilter::default() }
+    }
+
+    fn budgets() -> Vec<Revisits> {
+        vec!(
+            Revisits::Total(0), Revisits::Total(1), Revisits::Total(2),
+            Revisits::PerCave(1), Revisits::PerCave(2), Revisits::PerCave(3)
+        )
+    }
+
+    #[test]
+    fn example_counts() {
+        let caves = system(&["start-A", "start-b", "A-c", "A-b", "b-d", "A-end", "b-end"]);
+        assert_eq!(caves.number_paths(), 10);
+        assert_eq!(caves.number_paths_single_reentry(), 36);
+    }
+
+    #[test]
+    fn counts_match_enumerated_paths() {
+        let middle = ["a", "b", "c", "d", "A", "B"];
+        let mut rng = ChaCha8Rng::seed_from_u64(46);
+        for _ in 0..60 {
+            let mut edges : Vec<String> = vec!(
+                format!("start-{}", middle[rng.gen_range(0..middle.len())]),
+                format!("{}-end", middle[rng.gen_range(0..middle.len())])
+            );
+            for _ in 0..rng.gen_range(3..9) {
+                let (a, b) = (middle[rng.gen_range(0..middle.len())], middle[rng.gen_range(0..middle.len())]);
+                if a != b && (a.to_lowercase() == a || b.to_lowercase() == b) {
+                    edges.push(format!("{}-{}", a, b));
+                }
+            }
+            let caves = CaveSystem::from_lines(edges.clone());
+            for revisits in budgets() {
+                let enumerated = caves.paths(&filter(revisits)).map(|paths| paths.count());
+                assert_eq!(caves.count_paths(revisits), enumerated, "{:?} {:?}", revisits, edges);
+            }
+        }
+    }
+
+    #[test]
+    fn dot_names_are_escaped() {
+        let caves = system(&["start-a\"b", "a\"b-C\\d", "C\\d-end"]);
+        let dot = caves.to_dot();
+        assert!(dot.contains("    \"a\\\"b\" [label=\"a\\\"b\", shape=ellipse];\n"), "{}", dot);
+        assert!(dot.contains("    \"C\\\\d\" -- \"a\\\"b\";\n"), "{}", dot);
+    }
+}
@@ -2,48 +2,445 @@ pub struct Crab {
     pub pos : i64
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Alignment {
+    pub position : i64,
+    pub fuel : u64
+}
 
-fn distance(crabs : &Vec<Crab>, target : i64) -> u64 {
-    crabs.iter().map(
-        |crab| crab.to_target(target)
-    ).sum()
+pub trait FuelCost {
+    fn cost(&self, distance : u64) -> u64;
+
+    // Targets known to contain an optimum, if the cost function has a
+    // closed form. Otherwise the optimiser assumes the cost is convex.
+    fn candidates(&self, _positions : &[i64]) -> Option<Vec<i64>> {
+        None
+    }
+
+    // Costs that add up over parts of a distance let the Manhattan metric
+    // split into one problem per axis.
+    fn additive(&self) -> bool {
+        false
+    }
 }
 
+pub struct Linear;
 
-fn distance_quad(crabs : &Vec<Crab>, target : i64) -> u64 {
-    crabs.iter().map(
-        |crab| crab.to_target_quad(target)
+pub struct Triangular;
+
+pub struct Quadratic;
+
+pub struct Custom<F>(pub F);
+
+fn median(positions : &[i64]) -> i64 {
+    let mut sorted = positions.to_vec();
+    sorted.sort_unstable();
+    sorted[(sorted.len() - 1) / 2]
+}
+
+fn mean_floor(positions : &[i64]) -> i64 {
+    let sum : i64 = positions.iter().sum();
+    sum.div_euclid(positions.len() as i64)
+}
+
+impl FuelCost for Linear {
+    fn cost(&self, distance : u64) -> u64 {
+        distance
+    }
+
+    fn candidates(&self, positions : &[i64]) -> Option<Vec<i64>> {
+        Some(vec!(median(positions)))
+    }
+
+    fn additive(&self) -> bool {
+        true
+    }
+}
+
+impl FuelCost for Triangular {
+    fn cost(&self, distance : u64) -> u64 {
+        (distance * (distance + 1)) / 2
+    }
+
+    // The optimum is within a half of the mean
+    fn candidates(&self, positions : &[i64]) -> Option<Vec<i64>> {
+        let mean = mean_floor(positions);
+        Some(vec!(mean - 1, mean, mean + 1))
+    }
+}
+
+impl FuelCost for Quadratic {
+    fn cost(&self, distance : u64) -> u64 {
+        distance * distance
+    }
+
+    fn candidates(&self, positions : &[i64]) -> Option<Vec<i64>> {
+        let mean = mean_floor(positions);
+        Some(vec!(mean, mean + 1))
+    }
+}
+
+impl<F> FuelCost for Custom<F>
+    where F : Fn(u64) -> u64
+{
+    fn cost(&self, distance : u64) -> u64 {
+        (self.0)(distance)
+    }
+}
+
+fn fuel<C : FuelCost>(positions : &[i64], cost : &C, target : i64) -> u64 {
+    positions.iter().map(
+        |pos| cost.cost((pos - target).unsigned_abs())
     ).sum()
 }
 
+fn ternary_search<C : FuelCost>(positions : &[i64], cost : &C, mut low : i64, mut high : i64) -> i64 {
+    while high - low > 2 {
+        let left = low + (high - low) / 3;
+        let right = high - (high - low) / 3;
+        if fuel(positions, cost, left) <= fuel(positions, cost, right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    (low..(high+1)).min_by_key(
+        |target| (fuel(positions, cost, *target), *target)
+    ).unwrap()
+}
+
+pub fn align_positions<C : FuelCost>(positions : &[i64], cost : &C) -> Option<Alignment> {
+    let min_pos = *positions.iter().min()?;
+    let max_pos = *positions.iter().max()?;
+    let candidates = cost.candidates(positions).unwrap_or_else(
+        || vec!(ternary_search(positions, cost, min_pos, max_pos))
+    );
+    candidates.into_iter().map(
+        |target| Alignment { position : target, fuel : fuel(positions, cost, target) }
+    ).min_by_key(
+        |alignment| (alignment.fuel, alignment.position)
+    )
+}
+
+pub fn align<C : FuelCost>(crabs : &Vec<Crab>, cost : &C) -> Option<Alignment> {
+    let positions : Vec<i64> = crabs.iter().map(|crab| crab.pos).collect();
+    align_positions(&positions, cost)
+}
 
 pub fn minimum_distance(crabs : &Vec<Crab>) -> u64 {
-    let min_pos = crabs.iter().map(|crab| crab.pos).min().unwrap();
-    let max_pos = crabs.iter().map(|crab| crab.pos).max().unwrap();
-    (min_pos..(max_pos+1)).map(
-        |target| distance(crabs, target)
-    ).min().unwrap()
+    align(crabs, &Linear).map(|alignment| alignment.fuel).unwrap_or(0)
 }
 
 pub fn minimum_distance_quad(crabs : &Vec<Crab>) -> u64 {
-    let min_pos = crabs.iter().map(|crab| crab.pos).min().unwrap();
-    let max_pos = crabs.iter().map(|crab| crab.pos).max().unwrap();
-    (min_pos..(max_pos+1)).map(
-        |target| distance_quad(crabs, target)
-    ).min().unwrap()
+    align(crabs, &Triangular).map(|alignment| alignment.fuel).unwrap_or(0)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpatialCrab<const N : usize> {
+    pub pos : [i64; N]
+}
+
+// Euclidean distances are rounded up to whole steps before the cost is
+// applied, so a crab never moves less than the straight line to the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    PerAxis,
+    Manhattan,
+    Euclidean
+}
+
+// Capacity limits are how much fuel each crab can carry, so no crab may
+// need more than MaxFuelPerCrab to reach the target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Constraint<const N : usize> {
+    Forbidden { min : [i64; N], max : [i64; N] },
+    MaxFuelPerCrab(u64)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpatialAlignment<const N : usize> {
+    pub position : [i64; N],
+    pub fuel : u64
+}
+
+fn ceil_sqrt(value : u64) -> u64 {
+    let mut root = (value as f64).sqrt() as u64;
+    while root * root > value {
+        root -= 1;
+    }
+    while root * root < value {
+        root += 1;
+    }
+    root
+}
+
+fn euclidean_steps<const N : usize>(from : &[i64; N], to : &[i64; N]) -> u64 {
+    ceil_sqrt((0..N).map(
+        |axis| (from[axis] - to[axis]).unsigned_abs().pow(2)
+    ).sum())
+}
+
+impl Metric {
+    fn crab_fuel<C : FuelCost, const N : usize>(&self, cost : &C, from : &[i64; N], to : &[i64; N]) -> u64 {
+        let deltas = (0..N).map(|axis| (from[axis] - to[axis]).unsigned_abs());
+        match self {
+            Metric::PerAxis => deltas.map(|delta| cost.cost(delta)).sum(),
+            Metric::Manhattan => cost.cost(deltas.sum()),
+            Metric::Euclidean => cost.cost(euclidean_steps(from, to))
+        }
+    }
+
+    // Rounding Euclidean distances up breaks convexity of the total fuel
+    fn convex(&self) -> bool {
+        *self != Metric::Euclidean
+    }
+}
+
+impl<const N : usize> Constraint<N> {
+    fn allows(&self, target : &[i64; N], crab_fuels : &[u64]) -> bool {
+        match self {
+            Constraint::Forbidden { min, max } => !(0..N).all(
+                |axis| target[axis] >= min[axis] && target[axis] <= max[axis]
+            ),
+            Constraint::MaxFuelPerCrab(limit) => crab_fuels.iter().all(|fuel| fuel <= limit)
+        }
+    }
+}
+
+fn align_per_axis<C : FuelCost, const N : usize>(crabs : &[SpatialCrab<N>], cost : &C) -> Option<SpatialAlignment<N>> {
+    let mut position = [0; N];
+    let mut fuel = 0;
+    for axis in 0..N {
+        let positions : Vec<i64> = crabs.iter().map(|crab| crab.pos[axis]).collect();
+        let alignment = align_positions(&positions, cost)?;
+        position[axis] = alignment.position;
+        fuel += alignment.fuel;
+    }
+    Some(SpatialAlignment { position : position, fuel : fuel })
+}
+
+// Any target outside the box around the crabs and forbidden regions can be
+// moved onto the box without any crab getting further away, so for costs
+// that never decrease with distance it is enough to check the box.
+fn search_box<const N : usize>(crabs : &[SpatialCrab<N>], constraints : &[Constraint<N>]) -> Option<([i64; N], [i64; N])> {
+    let mut low = crabs.first()?.pos;
+    let mut high = low;
+    for crab in crabs.iter() {
+        for axis in 0..N {
+            low[axis] = std::cmp::min(low[axis], crab.pos[axis]);
+            high[axis] = std::cmp::max(high[axis], crab.pos[axis]);
+        }
+    }
+    for constraint in constraints.iter() {
+        if let Constraint::Forbidden { min, max } = constraint {
+            for axis in 0..N {
+                low[axis] = std::cmp::min(low[axis], min[axis] - 1);
+                high[axis] = std::cmp::max(high[axis], max[axis] + 1);
+            }
+        }
+    }
+    Some((low, high))
+}
+
+fn spatial_fuel<C : FuelCost, const N : usize>(crabs : &[SpatialCrab<N>], cost : &C, metric : Metric, target : &[i64; N]) -> (u64, Vec<u64>) {
+    let crab_fuels : Vec<u64> = crabs.iter().map(
+        |crab| metric.crab_fuel(cost, &crab.pos, target)
+    ).collect();
+    (crab_fuels.iter().sum(), crab_fuels)
+}
+
+// Ternary search on each axis in turn, minimising over the later axes for
+// every value tried, which is exact when the total fuel is convex.
+fn nested_search<C : FuelCost, const N : usize>(
+    crabs : &[SpatialCrab<N>],
+    cost : &C,
+    metric : Metric,
+    low : &[i64; N],
+    high : &[i64; N],
+    target : [i64; N],
+    axis : usize
+) -> SpatialAlignment<N> {
+    if axis == N {
+        return SpatialAlignment { position : target, fuel : spatial_fuel(crabs, cost, metric, &target).0 };
+    }
+    let best_with = |value : i64| {
+        let mut next = target;
+        next[axis] = value;
+        nested_search(crabs, cost, metric, low, high, next, axis + 1)
+    };
+    let (mut from, mut to) = (low[axis], high[axis]);
+    while to - from > 2 {
+        let left = from + (to - from) / 3;
+        let right = to - (to - from) / 3;
+        if best_with(left).fuel <= best_with(right).fuel {
+            to = right;
+        } else {
+            from = left;
+        }
+    }
+    (from..(to+1)).map(best_with).min_by_key(|alignment| alignment.fuel).unwrap()
 }
 
-impl Crab {
-    fn to_target(&self, target : i64) -> u64 {
-        (self.pos - target).abs() as u64
+// Points at exactly the given Chebyshev distance from the centre which are
+// inside the search box, taking the first axis at that distance as the face.
+fn ring<const N : usize>(centre : &[i64; N], radius : i64, low : &[i64; N], high : &[i64; N]) -> Vec<[i64; N]> {
+    if radius == 0 {
+        return vec!(*centre);
     }
+    let mut points = Vec::new();
+    for face in 0..N {
+        for side in [-radius, radius] {
+            let mut face_low = [0; N];
+            let mut face_high = [0; N];
+            for axis in 0..N {
+                let reach = if axis < face { radius - 1 } else { radius };
+                face_low[axis] = std::cmp::max(low[axis], centre[axis] - reach);
+                face_high[axis] = std::cmp::min(high[axis], centre[axis] + reach);
+            }
+            face_low[face] = centre[face] + side;
+            face_high[face] = centre[face] + side;
+            if (0..N).any(|axis| face_low[axis] > face_high[axis] || face_low[axis] < low[axis] || face_high[axis] > high[axis]) {
+                continue;
+            }
+            let mut point = face_low;
+            loop {
+                points.push(point);
+                if !next_target(&mut point, &face_low, &face_high) {
+                    break;
+                }
+            }
+        }
+    }
+    points
+}
+
+fn next_target<const N : usize>(target : &mut [i64; N], low : &[i64; N], high : &[i64; N]) -> bool {
+    for axis in 0..N {
+        if target[axis] < high[axis] {
+            target[axis] += 1;
+            return true;
+        }
+        target[axis] = low[axis];
+    }
+    false
+}
 
-    fn to_target_quad(&self, target : i64) -> u64 {
-        let diff = self.to_target(target);
-        if diff == 0 {
-            0
+// A point at Chebyshev distance radius or more from the centre is at least
+// radius minus the crab's own distance from the centre away from each crab.
+fn euclidean_bound<C : FuelCost, const N : usize>(crabs : &[SpatialCrab<N>], cost : &C, centre : &[i64; N], radius : i64) -> u64 {
+    crabs.iter().map(
+        |crab| cost.cost((radius as u64).saturating_sub(euclidean_steps(&crab.pos, centre)))
+    ).sum()
+}
+
+// The per axis metric, or Manhattan with an additive cost, splits into one
+// dimensional problems. Otherwise the best target ignoring constraints comes
+// from a nested search, then rings around it are checked until no point on
+// a ring can beat the best allowed target, as the fuel only grows moving
+// away from the unconstrained optimum when it is convex. Euclidean fuel
+// isn't convex, so rings are checked until a lower bound on the fuel of
+// anything further out is no better.
+pub fn align_spatial<C : FuelCost, const N : usize>(
+    crabs : &[SpatialCrab<N>],
+    cost : &C,
+    metric : Metric,
+    constraints : &[Constraint<N>]
+) -> Option<SpatialAlignment<N>> {
+    let splits = metric == Metric::PerAxis || (metric == Metric::Manhattan && cost.additive());
+    let (low, high) = search_box(crabs, constraints)?;
+    let optimum = if splits {
+        let mut alignment = align_per_axis(crabs, cost)?;
+        alignment.fuel = spatial_fuel(crabs, cost, metric, &alignment.position).0;
+        alignment
+    } else {
+        nested_search(crabs, cost, metric, &low, &high, low, 0)
+    };
+    if constraints.is_empty() && metric.convex() {
+        return Some(optimum);
+    }
+    let mut best : Option<SpatialAlignment<N>> = None;
+    for radius in 0.. {
+        let points = ring(&optimum.position, radius, &low, &high);
+        if points.is_empty() {
+            return best;
+        }
+        let mut ring_min : Option<u64> = None;
+        for target in points {
+            let (fuel, crab_fuels) = spatial_fuel(crabs, cost, metric, &target);
+            ring_min = Some(ring_min.map(|min| std::cmp::min(min, fuel)).unwrap_or(fuel));
+            let allowed = constraints.iter().all(|constraint| constraint.allows(&target, &crab_fuels));
+            if allowed && best.map(|alignment| fuel < alignment.fuel).unwrap_or(true) {
+                best = Some(SpatialAlignment { position : target, fuel : fuel });
+            }
+        }
+        let further = if metric.convex() {
+            ring_min
         } else {
-            (diff * (diff + 1)) / 2
+            Some(euclidean_bound(crabs, cost, &optimum.position, radius + 1))
+        };
+        if let (Some(alignment), Some(min)) = (best, further) {
+            if min >= alignment.fuel {
+                return best;
+            }
         }
     }
-}
\ No newline at end of file
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn brute_force<C : FuelCost>(crabs : &[SpatialCrab<2>], cost : &C, metric : Metric, constraints : &[Constraint<2>]) -> Option<u64> {
+        let (low, high) = search_box(crabs, constraints)?;
+        let mut best : Option<u64> = None;
+        for x in low[0]..(high[0]+1) {
+            for y in low[1]..(high[1]+1) {
+                let (fuel, crab_fuels) = spatial_fuel(crabs, cost, metric, &[x, y]);
+                let allowed = constraints.iter().all(|constraint| constraint.allows(&[x, y], &crab_fuels));
+                if allowed && best.map(|min| fuel < min).unwrap_or(true) {
+                    best = Some(fuel);
+                }
+            }
+        }
+        best
+    }
+
+    fn check<C : FuelCost>(cost : &C, seed : u64) {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for _ in 0..200 {
+            let crabs : Vec<SpatialCrab<2>> = (0..rng.gen_range(1..8)).map(
+                |_| SpatialCrab { pos : [rng.gen_range(0..20), rng.gen_range(0..20)] }
+            ).collect();
+            let mut constraints : Vec<Constraint<2>> = (0..rng.gen_range(0..3)).map(|_| {
+                let min = [rng.gen_range(0..20), rng.gen_range(0..20)];
+                Constraint::Forbidden { min : min, max : [min[0] + rng.gen_range(0..8), min[1] + rng.gen_range(0..8)] }
+            }).collect();
+            if rng.gen_range(0..3) == 0 {
+                constraints.push(Constraint::MaxFuelPerCrab(rng.gen_range(0..60)));
+            }
+            for metric in [Metric::PerAxis, Metric::Manhattan, Metric::Euclidean] {
+                let found = align_spatial(&crabs, cost, metric, &constraints).map(|alignment| alignment.fuel);
+                assert_eq!(found, brute_force(&crabs, cost, metric, &constraints), "{:?} {:?} {:?}", metric, crabs, constraints);
+            }
+        }
+    }
+
+    #[test]
+    fn spatial_alignment_is_optimal() {
+        check(&Linear, 1);
+        check(&Triangular, 2);
+        check(&Quadratic, 3);
+        check(&Custom(|distance : u64| distance * distance * distance), 4);
+    }
+
+    #[test]
+    fn example_alignment() {
+        let crabs : Vec<Crab> = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14].iter().map(|pos| Crab { pos : *pos }).collect();
+        assert_eq!(minimum_distance(&crabs), 37);
+        assert_eq!(minimum_distance_quad(&crabs), 168);
+    }
+}
@@ -1,135 +1,331 @@
+// Segments lit for each of the digits 0 to 9, with a the top bar, b c the
+// upper left and right, d the middle, e f the lower left and right and g
+// the bottom bar
+const DIGITS : [(char, &str); 10] = [
+    ('0', "abcefg"), ('1', "cf"), ('2', "acdeg"), ('3', "acdfg"), ('4', "bcdf"),
+    ('5', "abdfg"), ('6', "abdefg"), ('7', "acf"), ('8', "abcdefg"), ('9', "abcdfg")
+];
+
+const HEX_LETTERS : [(char, &str); 6] = [
+    ('A', "abcdef"), ('b', "bdefg"), ('C', "abeg"), ('d', "cdefg"), ('E', "abdeg"), ('F', "abde")
+];
+
+// Fourteen segments are a to f clockwise around the edge from the top, g and
+// h the left and right halves of the middle bar, i j k the upper diagonal,
+// centre and diagonal from left to right and l m n the same for the lower
+// half.
+const FOURTEEN_GLYPHS : [(char, &str); 36] = [
+    ('0', "abcdefkl"), ('1', "bc"), ('2', "abdegh"), ('3', "abcdh"), ('4', "bcfgh"),
+    ('5', "adfgn"), ('6', "acdefgh"), ('7', "abc"), ('8', "abcdefgh"), ('9', "abcdfgh"),
+    ('A', "abcefgh"), ('B', "abcdhjm"), ('C', "adef"), ('D', "abcdjm"), ('E', "adefgh"),
+    ('F', "aefg"), ('G', "acdefh"), ('H', "bcefgh"), ('I', "adjm"), ('J', "bcde"),
+    ('K', "efgkn"), ('L', "def"), ('M', "bcefik"), ('N', "bcefin"), ('O', "abcdef"),
+    ('P', "abefgh"), ('Q', "abcdefn"), ('R', "abefghn"), ('S', "acdfgh"), ('T', "ajm"),
+    ('U', "bcdef"), ('V', "efkl"), ('W', "bcefln"), ('X', "ikln"), ('Y', "ikm"), ('Z', "adkl")
+];
+
+// Sixteen segments split the top and bottom bars into halves, clockwise
+// from the top left these are a b top, c d right, e f bottom, g h left. Then
+// i j are the middle bar, k l m the upper diagonals and centre and n o p
+// the lower ones.
+const SIXTEEN_GLYPHS : [(char, &str); 36] = [
+    ('0', "abcdefghmn"), ('1', "cd"), ('2', "abcefgij"), ('3', "abcdefj"), ('4', "cdhij"),
+    ('5', "abefhip"), ('6', "abdefghij"), ('7', "abcd"), ('8', "abcdefghij"), ('9', "abcdefhij"),
+    ('A', "abcdghij"), ('B', "abcdefjlo"), ('C', "abefgh"), ('D', "abcdeflo"), ('E', "abefghij"),
+    ('F', "abghi"), ('G', "abdefghj"), ('H', "cdghij"), ('I', "abeflo"), ('J', "cdefg"),
+    ('K', "ghimp"), ('L', "efgh"), ('M', "cdghkm"), ('N', "cdghkp"), ('O', "abcdefgh"),
+    ('P', "abcghij"), ('Q', "abcdefghp"), ('R', "abcghijp"), ('S', "abdefhij"), ('T', "ablo"),
+    ('U', "cdefgh"), ('V', "ghmn"), ('W', "cdghnp"), ('X', "kmnp"), ('Y', "kmo"), ('Z', "abefmn")
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Alphabet {
+    segments : Vec<char>,
+    glyphs : Vec<(char, u32)>
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Display {
     bits : Vec<bool>
 }
 
-impl Display {
-    pub fn from_string(string : &str) -> Display {
-        Display { bits: vec!(
-            string.contains('a'),
-            string.contains('b'),
-            string.contains('c'),
-            string.contains('d'),
-            string.contains('e'),
-            string.contains('f'),
-            string.contains('g')
-         ) }
-    }
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Wiring {
+    pub segments : Vec<usize>
+}
 
-    fn bits_set(&self) -> usize {
-        self.bits.iter().filter(
-            |bit| **bit
-        ).count()
-    } 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WiringError {
+    Inconsistent,
+    Ambiguous(Vec<Wiring>),
+    NotANumber(String)
+}
+
+fn segment_mask(segments : &[char], glyph : &str) -> u32 {
+    segments.iter().enumerate().filter(
+        |(_, segment)| glyph.contains(**segment)
+    ).fold(0, |mask, (index, _)| mask | (1 << index))
+}
 
-    fn intersection(&self, other : &Self) -> Display {
-        let new_bits = self.bits.iter().zip(other.bits.iter()).map(
-            |(bit1, bit2)| *bit1 && *bit2
+impl Alphabet {
+    pub fn new(segments : &str, glyphs : &[(char, &str)]) -> Alphabet {
+        let segments : Vec<char> = segments.chars().collect();
+        let glyphs = glyphs.iter().map(
+            |(glyph, lit)| (*glyph, segment_mask(&segments, lit))
         ).collect();
-        Display { bits : new_bits }
+        Alphabet { segments : segments, glyphs : glyphs }
+    }
+
+    pub fn seven_segment() -> Alphabet {
+        Alphabet::new("abcdefg", &DIGITS)
+    }
+
+    pub fn seven_segment_hex() -> Alphabet {
+        let glyphs : Vec<(char, &str)> = DIGITS.iter().chain(HEX_LETTERS.iter()).cloned().collect();
+        Alphabet::new("abcdefg", &glyphs)
     }
 
-    fn is_easy_digit(&self) -> bool {
-       self.is_one() || self.is_four() || self.is_seven() || self.is_eight()
+    pub fn fourteen_segment() -> Alphabet {
+        Alphabet::new("abcdefghijklmn", &FOURTEEN_GLYPHS)
     }
 
-    fn is_zero(&self, six : &Self, nine : &Self) -> bool {
-        self.bits_set() == 6 && *self != *six && *self != *nine
+    pub fn sixteen_segment() -> Alphabet {
+        Alphabet::new("abcdefghijklmnop", &SIXTEEN_GLYPHS)
     }
 
-    fn is_one(&self) -> bool {
-        self.bits_set() == 2
+    fn masks(&self) -> Vec<u32> {
+        self.glyphs.iter().map(|(_, mask)| *mask).collect()
     }
 
-    fn is_two(&self, three : &Self, four : &Self) -> bool {
-        self.bits_set() == 5 && *self != *three && self.intersection(four).bits_set() == 2
+    fn full(&self) -> u32 {
+        (1 << self.segments.len()) - 1
     }
 
-    fn is_three(&self, one : &Self) -> bool {
-        self.bits_set() == 5 && self.intersection(one) == *one
+    // A glyph is easy to spot when no other glyph lights as many segments
+    fn is_easy(&self, lit : u32) -> bool {
+        self.glyphs.iter().filter(
+            |(_, mask)| mask.count_ones() == lit
+        ).count() == 1
+    }
+}
+
+impl Display {
+    pub fn from_string(string : &str, alphabet : &Alphabet) -> Display {
+        Display { bits: alphabet.segments.iter().map(
+            |segment| string.contains(*segment)
+        ).collect() }
+    }
+
+    fn bits_set(&self) -> usize {
+        self.bits.iter().filter(
+            |bit| **bit
+        ).count()
     }
 
-    fn is_four(&self) -> bool {
-        self.bits_set() == 4
+    fn mask(&self) -> u32 {
+        self.bits.iter().enumerate().filter(
+            |(_, bit)| **bit
+        ).fold(0, |mask, (index, _)| mask | (1 << index))
     }
 
-    fn is_five(&self, three : &Self, four : &Self) -> bool {
-        self.bits_set() == 5 && *self != *three && self.intersection(four).bits_set() == 3
+    fn is_easy_glyph(&self, alphabet : &Alphabet) -> bool {
+        alphabet.is_easy(self.bits_set() as u32)
     }
+}
 
-    fn is_six(&self, one : &Self) -> bool {
-        self.bits_set() == 6 && self.intersection(one) != *one
+impl Wiring {
+    fn map_mask(&self, mask : u32) -> u32 {
+        self.segments.iter().enumerate().filter(
+            |(wire, _)| mask & (1 << wire) != 0
+        ).fold(0, |mapped, (_, segment)| mapped | (1 << segment))
     }
 
-    fn is_seven(&self) -> bool {
-        self.bits_set() == 3
+    pub fn decode(&self, display : &Display, alphabet : &Alphabet) -> Option<char> {
+        let mapped = self.map_mask(display.mask());
+        alphabet.glyphs.iter().find(
+            |(_, mask)| *mask == mapped
+        ).map(|(glyph, _)| *glyph)
     }
+}
 
-    fn is_eight(&self) -> bool {
-        self.bits_set() == 7
+// Narrows the segments each wire could drive until nothing changes. A
+// pattern can only be a glyph with the same number of segments, which
+// restricts its wires to that glyph's segments and every other wire to the
+// remaining segments.
+fn propagate(domains : &mut Vec<u32>, observations : &[u32], glyphs : &[u32], full : u32) -> bool {
+    loop {
+        let before = domains.clone();
+        for observation in observations.iter() {
+            let candidates : Vec<u32> = glyphs.iter().filter(
+                |glyph| glyph.count_ones() == observation.count_ones()
+            ).filter(
+                |glyph| domains.iter().enumerate().all(
+                    |(wire, domain)| if observation & (1 << wire) != 0 {
+                        domain & **glyph != 0
+                    } else {
+                        domain & !**glyph & full != 0
+                    }
+                )
+            ).cloned().collect();
+            let inside = candidates.iter().fold(0, |acc, glyph| acc | glyph);
+            let outside = candidates.iter().fold(0, |acc, glyph| acc | (!glyph & full));
+            for (wire, domain) in domains.iter_mut().enumerate() {
+                *domain &= if observation & (1 << wire) != 0 { inside } else { outside };
+            }
+        }
+        for wire in 0..domains.len() {
+            if domains[wire].count_ones() == 1 {
+                let fixed = domains[wire];
+                for (other, domain) in domains.iter_mut().enumerate() {
+                    if other != wire {
+                        *domain &= !fixed;
+                    }
+                }
+            }
+        }
+        if domains.iter().any(|domain| *domain == 0) {
+            return false;
+        }
+        if *domains == before {
+            return true;
+        }
     }
+}
 
-    fn is_nine(&self, four : &Self) -> bool {
-        self.bits_set() == 6 && self.intersection(four) == *four
+// Collects wirings consistent with the observations, stopping once the
+// limit is reached.
+fn search(
+    domains : Vec<u32>,
+    observations : &[u32],
+    glyphs : &[u32],
+    full : u32,
+    wirings : &mut Vec<Wiring>,
+    limit : usize
+) {
+    let mut domains = domains;
+    if wirings.len() >= limit || !propagate(&mut domains, observations, glyphs, full) {
+        return;
+    }
+    let open_wire = (0..domains.len()).filter(
+        |wire| domains[*wire].count_ones() > 1
+    ).min_by_key(|wire| domains[*wire].count_ones());
+    match open_wire {
+        None => {
+            let wiring = Wiring {
+                segments : domains.iter().map(|domain| domain.trailing_zeros() as usize).collect()
+            };
+            let valid = observations.iter().all(
+                |observation| glyphs.contains(&wiring.map_mask(*observation))
+            );
+            if valid {
+                wirings.push(wiring);
+            }
+        },
+        Some(wire) => {
+            for segment in 0..domains.len() {
+                if domains[wire] & (1 << segment) != 0 {
+                    let mut choice = domains.clone();
+                    choice[wire] = 1 << segment;
+                    search(choice, observations, glyphs, full, wirings, limit);
+                }
+            }
+        }
     }
 }
 
 pub struct Entry {
-    patterns : [Display; 10],
-    output : [Display; 4]
+    alphabet : Alphabet,
+    patterns : Vec<Display>,
+    output : Vec<Display>
 }
 
 impl Entry {
     pub fn from_string(string : &str) -> Option<Entry> {
-        if let Some((pattern_s, output_s)) = string.split_once(" | ") {
-            let pattern_v : Vec<Display> = pattern_s.split(" ").map(
-                |str| Display::from_string(str)
+        Entry::from_string_with(string, &Alphabet::seven_segment())
+    }
+
+    pub fn from_string_with(string : &str, alphabet : &Alphabet) -> Option<Entry> {
+        if let Some((pattern_s, output_s)) = string.split_once("|") {
+            let patterns : Vec<Display> = pattern_s.split_whitespace().map(
+                |str| Display::from_string(str, alphabet)
             ).collect();
-            let output_v : Vec<Display> = output_s.split(" ").map(
-                |str| Display::from_string(str)
+            let output : Vec<Display> = output_s.split_whitespace().map(
+                |str| Display::from_string(str, alphabet)
             ).collect();
-            if let (Ok(patterns), Ok(output)) = (pattern_v.try_into(), output_v.try_into()) {
-                return Some(Entry{patterns : patterns, output : output});
-            }
+            return Some(Entry{alphabet : alphabet.clone(), patterns : patterns, output : output});
         }
         None
     }
 
     fn count_easy_digits(&self) -> usize {
         self.output.iter().filter(
-            |digit| digit.is_easy_digit()
+            |display| display.is_easy_glyph(&self.alphabet)
         ).count()
     }
 
-    fn map_digits(&self) -> [Display; 10] {
-        let one = self.patterns.iter().filter(|d| d.is_one()).next().unwrap().clone();
-        let four = self.patterns.iter().filter(|d| d.is_four()).next().unwrap().clone();
-        let seven = self.patterns.iter().filter(|d| d.is_seven()).next().unwrap().clone();
-        let eight= self.patterns.iter().filter(|d| d.is_eight()).next().unwrap().clone();
-        let nine = self.patterns.iter().filter(|d| d.is_nine(&four)).next().unwrap().clone();
-        let three = self.patterns.iter().filter(|d| d.is_three(&one)).next().unwrap().clone();
-        let six = self.patterns.iter().filter(|d| d.is_six(&one)).next().unwrap().clone();
-        let zero = self.patterns.iter().filter(|d| d.is_zero(&six, &nine)).next().unwrap().clone();
-        let two = self.patterns.iter().filter(|d| d.is_two(&three, &four)).next().unwrap().clone();
-        let five = self.patterns.iter().filter(|d| d.is_five(&three, &four)).next().unwrap().clone();
-        [zero, one, two, three, four, five, six, seven, eight, nine]
-    }
-
-    fn output_nums(&self) -> Vec<usize> {
-        let map = self.map_digits();
-        self.output.iter().map(
-            |digit| map.iter().position(|test| *test == *digit).unwrap()
+    fn observations(&self) -> Vec<u32> {
+        self.patterns.iter().chain(self.output.iter()).map(
+            |display| display.mask()
         ).collect()
     }
 
-    pub fn output_num(&self) -> usize {
-        let mut res : usize = 0;
-        let mut base : usize = 1;
-        for d in self.output_nums().into_iter().rev() {
-            res += base * d;
-            base *= 10;
+    fn find_wirings(&self, domains : Vec<u32>, limit : usize) -> Vec<Wiring> {
+        let mut wirings = Vec::new();
+        search(domains, &self.observations(), &self.alphabet.masks(), self.alphabet.full(), &mut wirings, limit);
+        wirings
+    }
+
+    fn all_domains(&self) -> Vec<u32> {
+        vec!(self.alphabet.full(); self.alphabet.segments.len())
+    }
+
+    pub fn wirings(&self) -> Vec<Wiring> {
+        self.find_wirings(self.all_domains(), usize::MAX)
+    }
+
+    // An ambiguous entry reports two of the wirings that fit.
+    pub fn solve(&self) -> Result<Wiring, WiringError> {
+        let mut wirings = self.find_wirings(self.all_domains(), 2);
+        match wirings.len() {
+            0 => Err(WiringError::Inconsistent),
+            1 => Ok(wirings.remove(0)),
+            _ => Err(WiringError::Ambiguous(wirings))
         }
-        res
+    }
+
+    // Several wirings can still agree on the output glyphs, so instead of
+    // listing them try each glyph at each output position and only report
+    // ambiguity when two glyphs are possible at the same place.
+    pub fn output_string(&self) -> Result<String, WiringError> {
+        let mut output = String::new();
+        for display in self.output.iter() {
+            let mask = display.mask();
+            let options : Vec<(char, Wiring)> = self.alphabet.glyphs.iter().filter(
+                |(_, glyph)| glyph.count_ones() == mask.count_ones()
+            ).filter_map(
+                |(glyph_char, glyph)| {
+                    let domains = self.all_domains().into_iter().enumerate().map(
+                        |(wire, domain)| if mask & (1 << wire) != 0 { domain & glyph } else { domain & !glyph }
+                    ).collect();
+                    self.find_wirings(domains, 1).pop().map(|wiring| (*glyph_char, wiring))
+                }
+            ).take(2).collect();
+            match options.len() {
+                0 => return Err(WiringError::Inconsistent),
+                1 => output.push(options[0].0),
+                _ => return Err(WiringError::Ambiguous(
+                    options.into_iter().map(|(_, wiring)| wiring).collect()
+                ))
+            }
+        }
+        Ok(output)
+    }
+
+    pub fn output_num(&self) -> Result<usize, WiringError> {
+        let output = self.output_string()?;
+        output.parse::<usize>().map_err(
+            |_| WiringError::NotANumber(output)
+        )
     }
 }
 
@@ -137,4 +333,4 @@ pub fn count_easy_digits(entries : &Vec<Entry>) -> usize {
     entries.iter().map(
         |entry| entry.count_easy_digits()
     ).sum()
-}
\ No newline at end of file
+}
@@ -1,41 +1,243 @@
-use std::collections::HashMap;
+use std::ops::{Add, Mul};
+
+use num_bigint::BigUint;
+use num_traits::{Zero, One};
+
+use super::transition::{Transition, Checked};
+
+// The model is a dense matrix over timer or age states, so lifecycles and
+// timers are kept to this many states.
+pub const MAX_STATES : u64 = 256;
 
 pub struct Fish {
     pub remaining : u64
 }
 
-struct FishChildrenCounter {
-    child_count_cache : HashMap<u64, u64>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lifecycle {
+    pub cycle_length : u64,
+    pub maturation_delay : u64,
+    pub mortality_age : Option<u64>,
+    pub litter_size : u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Population<T> {
+    pub counts : Vec<T>
+}
+
+// The states of the transition matrix along with the timer value of a fish
+// in each state.
+struct Model<T> {
+    transition : Transition<T>,
+    timers : Vec<u64>,
+    lifecycle : Lifecycle,
+    pending_start : usize
 }
 
-impl FishChildrenCounter {
-    fn init() -> FishChildrenCounter {
-        FishChildrenCounter { child_count_cache : HashMap::new() }
+impl Default for Lifecycle {
+    fn default() -> Lifecycle {
+        Lifecycle::lanternfish()
+    }
+}
+
+impl Lifecycle {
+    pub fn new(
+        cycle_length : u64,
+        maturation_delay : u64,
+        mortality_age : Option<u64>,
+        litter_size : u64
+    ) -> Option<Lifecycle> {
+        let lifecycle = Lifecycle {
+            cycle_length : cycle_length,
+            maturation_delay : maturation_delay,
+            mortality_age : mortality_age,
+            litter_size : litter_size
+        };
+        if lifecycle.is_valid() { Some(lifecycle) } else { None }
     }
 
-    fn number_children(&mut self, fish : &Fish, day : u64) -> u64 {
-        if fish.remaining > day {
-            0
+    pub fn lanternfish() -> Lifecycle {
+        Lifecycle { cycle_length : 7, maturation_delay : 2, mortality_age : None, litter_size : 1 }
+    }
+
+    // A fish has to wait at least a day between litters, and the timers
+    // and ages have to fit in MAX_STATES.
+    pub fn is_valid(&self) -> bool {
+        self.cycle_length > 0
+            && self.cycle_length.saturating_add(self.maturation_delay) <= MAX_STATES
+            && self.mortality_age.map(|age| age <= MAX_STATES).unwrap_or(true)
+    }
+
+    fn newborn_timer(&self) -> u64 {
+        self.cycle_length + self.maturation_delay - 1
+    }
+
+    fn timer_at_age(&self, age : u64) -> u64 {
+        let newborn = self.newborn_timer();
+        if age <= newborn {
+            newborn - age
         } else {
-            let spawn_day = day - fish.remaining;
-            if let Some(count) = self.child_count_cache.get(&spawn_day) {
-                *count
-            } else {
-                // One higher to include this day
-                let new_fish = Fish { remaining : 9 };
-                let reset_fish = Fish {remaining : 7};
-                let children = 1 + self.number_children(&new_fish, spawn_day)
-                                     + self.number_children(&reset_fish, spawn_day);
-                self.child_count_cache.insert(spawn_day, children);
-                children
+            self.cycle_length - 1 - (age - newborn - 1) % self.cycle_length
+        }
+    }
+
+    // Fish in the input only have a timer so take the youngest age that
+    // would have that timer, which is during the first countdown.
+    fn initial_age(&self, remaining : u64) -> u64 {
+        self.newborn_timer() - remaining
+    }
+}
+
+impl<T> Population<T>
+    where T : Clone + Zero
+{
+    pub fn total(&self) -> T {
+        self.counts.iter().cloned().fold(T::zero(), |acc, count| acc + count)
+    }
+}
+
+impl<T> Model<T>
+    where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T> + From<u64>
+{
+    // Without a mortality age the state is just the timer. Fish that die
+    // need their age tracking, so the states are then ages with extra
+    // countdown states for input fish with timers longer than a newborn's.
+    fn new(lifecycle : &Lifecycle, max_remaining : u64) -> Model<T> {
+        let newborn = lifecycle.newborn_timer();
+        let litter : T = T::from(lifecycle.litter_size);
+        match lifecycle.mortality_age {
+            None => {
+                let states = std::cmp::max(newborn, max_remaining) as usize + 1;
+                let mut transition = Transition::zero(states);
+                for timer in 1..states {
+                    transition.add_entry(timer - 1, timer, T::one());
+                }
+                transition.add_entry(lifecycle.cycle_length as usize - 1, 0, T::one());
+                transition.add_entry(newborn as usize, 0, litter);
+                Model {
+                    transition : transition,
+                    timers : (0..states as u64).collect(),
+                    lifecycle : lifecycle.clone(),
+                    pending_start : states
+                }
+            },
+            Some(mortality_age) => {
+                let ages = mortality_age as usize;
+                let pending = max_remaining.saturating_sub(newborn) as usize;
+                let mut transition = Transition::zero(ages + pending);
+                for age in 0..ages {
+                    if lifecycle.timer_at_age(age as u64) == 0 {
+                        transition.add_entry(0, age, litter.clone());
+                    }
+                    if age + 1 < ages {
+                        transition.add_entry(age + 1, age, T::one());
+                    }
+                }
+                for index in 0..pending {
+                    if index > 0 {
+                        transition.add_entry(ages + index - 1, ages + index, T::one());
+                    } else if ages > 0 {
+                        transition.add_entry(0, ages, T::one());
+                    }
+                }
+                let timers = (0..ages as u64).map(
+                    |age| lifecycle.timer_at_age(age)
+                ).chain(
+                    (0..pending as u64).map(|index| newborn + 1 + index)
+                ).collect();
+                Model {
+                    transition : transition,
+                    timers : timers,
+                    lifecycle : lifecycle.clone(),
+                    pending_start : ages
+                }
             }
         }
     }
+
+    fn state(&self, remaining : u64) -> Option<usize> {
+        let newborn = self.lifecycle.newborn_timer();
+        match self.lifecycle.mortality_age {
+            None => Some(remaining as usize),
+            Some(_) if remaining > newborn => Some(self.pending_start + (remaining - newborn - 1) as usize),
+            Some(_) => {
+                let age = self.lifecycle.initial_age(remaining) as usize;
+                if age < self.pending_start { Some(age) } else { None }
+            }
+        }
+    }
+
+    fn histogram_after(&self, fish : &Vec<Fish>, days : u64) -> Population<T> {
+        let mut counts = vec!(T::zero(); self.transition.states());
+        for state in fish.iter().filter_map(|single_fish| self.state(single_fish.remaining)) {
+            counts[state] = counts[state].clone() + T::one();
+        }
+        let final_counts = self.transition.pow(days).apply(&counts);
+        let max_timer = self.timers.iter().max().cloned().unwrap_or(0) as usize;
+        let mut histogram = vec!(T::zero(); max_timer + 1);
+        for (timer, count) in self.timers.iter().zip(final_counts.into_iter()) {
+            histogram[*timer as usize] = histogram[*timer as usize].clone() + count;
+        }
+        Population { counts : histogram }
+    }
+}
+
+// None when the lifecycle isn't valid or a timer is over MAX_STATES
+pub fn histogram_after<T>(fish : &Vec<Fish>, days : u64, lifecycle : &Lifecycle) -> Option<Population<T>>
+    where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T> + From<u64>
+{
+    let max_remaining = fish.iter().map(|single_fish| single_fish.remaining).max().unwrap_or(0);
+    if !lifecycle.is_valid() || max_remaining > MAX_STATES {
+        return None;
+    }
+    let model : Model<T> = Model::new(lifecycle, max_remaining);
+    Some(model.histogram_after(fish, days))
+}
+
+// The day given is the last day counted, so the population is taken after
+// one more step than the day number. None if the count doesn't fit in a u64.
+pub fn count_after(fish : &Vec<Fish>, day : u64, lifecycle : &Lifecycle) -> Option<u64> {
+    histogram_after::<Checked>(fish, day.checked_add(1)?, lifecycle)?.total().0
+}
+
+pub fn count_after_exact(fish : &Vec<Fish>, day : u64, lifecycle : &Lifecycle) -> Option<BigUint> {
+    histogram_after::<BigUint>(fish, day.checked_add(1)?, lifecycle).map(|population| population.total())
 }
 
-pub fn count_after(fish : &Vec<Fish>, day : u64) -> u64 {
-    let mut fish_children_counter = FishChildrenCounter::init();
-    fish.iter().map(
-        |fish| 1 + fish_children_counter.number_children(fish, day)
-    ).sum()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Vec<Fish> {
+        [3, 4, 3, 1, 2].iter().map(|remaining| Fish { remaining : *remaining }).collect()
+    }
+
+    #[test]
+    fn example_counts() {
+        let lanternfish = Lifecycle::lanternfish();
+        assert_eq!(count_after(&example(), 17, &lanternfish), Some(26));
+        assert_eq!(count_after(&example(), 79, &lanternfish), Some(5934));
+        assert_eq!(count_after(&example(), 255, &lanternfish), Some(26984457539));
+    }
+
+    #[test]
+    fn overflowing_count_is_none() {
+        let fish = vec!(Fish { remaining : 3 });
+        let lanternfish = Lifecycle::lanternfish();
+        assert_eq!(count_after(&fish, 1000, &lanternfish), None);
+        assert!(count_after_exact(&fish, 1000, &lanternfish).is_some());
+    }
+
+    #[test]
+    fn invalid_lifecycles_are_rejected() {
+        let fish = example();
+        assert_eq!(Lifecycle::new(0, 2, None, 1), None);
+        assert_eq!(Lifecycle::new(MAX_STATES + 1, 0, None, 1), None);
+        assert_eq!(Lifecycle::new(7, 2, Some(MAX_STATES + 1), 1), None);
+        let broken = Lifecycle { cycle_length : 0, maturation_delay : 0, mortality_age : None, litter_size : 1 };
+        assert_eq!(count_after(&fish, 10, &broken), None);
+        let huge = Lifecycle { cycle_length : 100_000, ..Lifecycle::lanternfish() };
+        assert_eq!(count_after(&fish, 10, &huge), None);
+    }
+}
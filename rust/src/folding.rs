@@ -1,23 +1,19 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, HashMap, BTreeSet};
 
-struct Paper {
-    dots : HashSet<(i64, i64)>
-}
+use super::ocr;
 
-fn fold_x_pt(x : i64, y : i64, val : i64) -> (i64, i64) {
-    if x > val {
-        (2*val - x, y)
-    } else {
-        (x,y)
-    }
+// Each dot knows which dots on the original paper ended up on it
+struct Paper {
+    dots : HashSet<(i64, i64)>,
+    origins : HashMap<(i64, i64), BTreeSet<(i64, i64)>>,
+    history : Vec<Folded>
 }
 
-fn fold_y_pt(x : i64, y : i64, val : i64) -> (i64, i64) {
-    if y > val {
-        (x, 2*val - y)
-    } else {
-        (x,y)
-    }
+// The dots before a fold that landed on each dot after it, along with the
+// origins before the fold so it can be undone.
+struct Folded {
+    sources : HashMap<(i64, i64), Vec<(i64, i64)>>,
+    origins : HashMap<(i64, i64), BTreeSet<(i64, i64)>>
 }
 
 impl Paper {
@@ -29,27 +25,31 @@ impl Paper {
                 |x| y_s.parse::<i64>().ok().map(|y| (x,y))
             )
         ).collect();
-        Paper {dots : dots}
-    }
-
-    fn fold_x(&mut self, val :i64) {
-        let new_dots : HashSet<(i64,i64)> = self.dots.iter().map(
-            |(x,y)| fold_x_pt(*x, *y, val)
+        let origins = dots.iter().map(
+            |dot| (*dot, BTreeSet::from([*dot]))
         ).collect();
-        self.dots = new_dots;
+        Paper {dots : dots, origins : origins, history : Vec::new()}
     }
 
-    fn fold_y(&mut self, val :i64) {
-        let new_dots : HashSet<(i64,i64)> = self.dots.iter().map(
-            |(x,y)| fold_y_pt(*x, *y, val)
+    fn fold(&mut self, fold : &Fold) {
+        let mut sources : HashMap<(i64, i64), Vec<(i64, i64)>> = HashMap::new();
+        for dot in self.dots.iter() {
+            sources.entry(fold.apply(*dot)).or_insert_with(Vec::new).push(*dot);
+        }
+        let origins = sources.iter().map(
+            |(dot, from)| (*dot, from.iter().flat_map(|source| self.origins[source].iter().cloned()).collect())
         ).collect();
-        self.dots = new_dots;
+        self.dots = sources.keys().cloned().collect();
+        self.history.push(Folded { sources : sources, origins : std::mem::replace(&mut self.origins, origins) });
     }
 
-    fn fold(&mut self, fold : &Fold) {
-        match fold {
-            Fold::X(val) => self.fold_x(*val),
-            Fold::Y(val) => self.fold_y(*val)
+    fn unfold(&mut self) -> bool {
+        if let Some(folded) = self.history.pop() {
+            self.dots = folded.sources.values().flatten().cloned().collect();
+            self.origins = folded.origins;
+            true
+        } else {
+            false
         }
     }
 
@@ -80,23 +80,38 @@ impl Paper {
     }
 }
 
-enum Fold {
+// Diagonal folds are along y - x = c and anti-diagonal ones along
+// x + y = c, which both take whole dots to whole dots. The side with the
+// larger value is folded over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fold {
     X(i64),
-    Y(i64)
+    Y(i64),
+    Diagonal(i64),
+    AntiDiagonal(i64)
 }
 
 impl Fold {
     fn from_string(string : &str) -> Option<Fold> {
-        if let Some( (dir_s, val_s) ) = string.split_once('=') {
-            if let Some(val) = val_s.parse::<i64>().ok() {
-                if dir_s.ends_with('x') {
-                    return Some(Fold::X(val))
-                } else {
-                    return Some(Fold::Y(val))
-                }
-            }
+        let (dir_s, val_s) = string.trim().trim_start_matches("fold along").trim().split_once('=')?;
+        let val = val_s.trim().parse::<i64>().ok()?;
+        match dir_s.trim() {
+            "x" => Some(Fold::X(val)),
+            "y" => Some(Fold::Y(val)),
+            "y-x" => Some(Fold::Diagonal(val)),
+            "x+y" => Some(Fold::AntiDiagonal(val)),
+            _ => None
+        }
+    }
+
+    fn apply(&self, (x, y) : (i64, i64)) -> (i64, i64) {
+        match self {
+            Fold::X(val) if x > *val => (2*val - x, y),
+            Fold::Y(val) if y > *val => (x, 2*val - y),
+            Fold::Diagonal(val) if y - x > *val => (y - val, x + val),
+            Fold::AntiDiagonal(val) if x + y > *val => (val - y, val - x),
+            _ => (x, y)
         }
-        None
     }
 }
 
@@ -106,16 +121,16 @@ pub struct Instructions {
 }
 
 impl Instructions {
+    // Fails if any fold line isn't along a known kind of line
     pub fn from_string(string : &str) -> Option<Instructions> {
-        string.split_once("\n\n").map(
-            |(paper_s, folds_s)| {  
-                let paper = Paper::from_string(paper_s);
-                let folds :Vec<Fold> = folds_s.split('\n').filter_map(
-                    |fold_s| Fold::from_string(fold_s)
-                ).collect();
-                Instructions {paper: paper, folds : folds}
-            }
-        )
+        let (paper_s, folds_s) = string.split_once("\n\n")?;
+        let paper = Paper::from_string(paper_s);
+        let folds : Vec<Fold> = folds_s.split('\n').filter(
+            |fold_s| !fold_s.trim().is_empty()
+        ).map(
+            |fold_s| Fold::from_string(fold_s)
+        ).collect::<Option<Vec<Fold>>>()?;
+        Some(Instructions {paper: paper, folds : folds})
     }
 
     pub fn fold_first(&mut self) {
@@ -128,10 +143,31 @@ impl Instructions {
         }
     }
 
+    pub fn fold_with(&mut self, fold : &Fold) {
+        self.paper.fold(fold);
+    }
+
+    // Goes back to before the last fold, false if nothing is folded
+    pub fn unfold(&mut self) -> bool {
+        self.paper.unfold()
+    }
+
+    pub fn origins(&self, dot : (i64, i64)) -> Option<&BTreeSet<(i64, i64)>> {
+        self.paper.origins.get(&dot)
+    }
+
     pub fn display(&self) {
         self.paper.display();
     }
 
+    pub fn dots(&self) -> &HashSet<(i64, i64)> {
+        &self.paper.dots
+    }
+
+    pub fn code(&self) -> Result<String, ocr::OcrError> {
+        ocr::recognise(&self.paper.dots)
+    }
+
     pub fn number_dots(&self) -> usize {
         self.paper.dots.len()
     }
@@ -1,10 +1,115 @@
-use std::collections::{HashSet, BTreeSet};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::cmp::Reverse;
 
 use itertools::Itertools;
 
 #[derive(Debug)]
 pub struct HeightMap {
-    heights : Vec<Vec<u8>>
+    heights : Vec<Vec<u8>>,
+    ridge : u8
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Basin {
+    pub id : usize,
+    pub size : usize,
+    pub low_point : (usize, usize),
+    pub depth : u8,
+    pub perimeter : usize
+}
+
+#[derive(Clone, Debug)]
+pub struct BasinMap {
+    labels : Vec<Vec<Option<usize>>>,
+    basins : Vec<Basin>,
+    adjacent : BTreeSet<(usize, usize)>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lake {
+    pub low_points : Vec<(usize, usize)>,
+    pub water : u64,
+    pub capacity : u64,
+    pub spill_height : u8
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rainfall {
+    pub lakes : Vec<Lake>,
+    pub outflow : u64
+}
+
+// Where water on each cell ends up, worked out once for a map. Sinks are
+// named by their first cell, which for a single cell is its low point.
+#[derive(Clone, Debug)]
+pub struct Catchments {
+    sinks : Vec<(usize, usize)>,
+    cells : Vec<Vec<(usize, usize)>>,
+    catchment : HashMap<(usize, usize), usize>
+}
+
+// Where water leaves a lake once it is full, either over the edge of the
+// map or into the catchment of another sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Spill {
+    OffMap,
+    Into(usize)
+}
+
+fn find_root(parents : &mut Vec<usize>, index : usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
+
+fn union(parents : &mut Vec<usize>, a : usize, b : usize) {
+    let root_a = find_root(parents, a);
+    let root_b = find_root(parents, b);
+    if root_a != root_b {
+        parents[std::cmp::max(root_a, root_b)] = std::cmp::min(root_a, root_b);
+    }
+}
+
+impl BasinMap {
+    pub fn label(&self, i : usize, j : usize) -> Option<usize> {
+        self.labels.get(i).and_then(|row| row.get(j).cloned().flatten())
+    }
+
+    pub fn basins(&self) -> &Vec<Basin> {
+        &self.basins
+    }
+
+    pub fn adjacent(&self) -> &BTreeSet<(usize, usize)> {
+        &self.adjacent
+    }
+}
+
+impl Catchments {
+    pub fn sinks(&self) -> &Vec<(usize, usize)> {
+        &self.sinks
+    }
+
+    // The sink water on a cell ends up in, None on a flat at or above the
+    // ridge with nowhere lower to go.
+    pub fn drain_to(&self, i : usize, j : usize) -> Option<(usize, usize)> {
+        self.catchment.get(&(i, j)).map(|id| self.sinks[*id])
+    }
+
+    fn id(&self, cell : &(usize, usize)) -> Option<usize> {
+        self.catchment.get(cell).cloned()
+    }
+
+    fn cells_of(&self, ids : &BTreeSet<usize>) -> Vec<(usize, usize)> {
+        ids.iter().flat_map(|id| self.cells[*id].iter().cloned()).collect()
+    }
 }
 
 impl HeightMap {
@@ -13,10 +118,16 @@ impl HeightMap {
         HeightMap {
             heights : lines.iter().map(
                 |line| line.chars().filter_map(|c| c.to_digit(10).map(|n| n as u8)).collect()
-            ).collect()
+            ).collect(),
+            ridge : 9
         }
     }
 
+    pub fn with_ridge(mut self, ridge : u8) -> HeightMap {
+        self.ridge = ridge;
+        self
+    }
+
     fn size(&self) -> (usize, usize) {
         if self.heights.is_empty() {
             (0,0)
@@ -70,36 +181,78 @@ impl HeightMap {
 
     fn non_edge_neighbours(&self, i : usize, j : usize) -> Vec<(usize, usize)> {
         self.neighbour_indices(i, j).into_iter().filter(
-            |(n_i, n_j)| self.get(*n_i, *n_j).filter(|val| *val != 9).is_some()
+            |(n_i, n_j)| self.get(*n_i, *n_j).filter(|val| *val < self.ridge).is_some()
         ).collect()
     }
     
 
-    fn low_points(&self) -> Vec<(usize, usize)> {
+    pub fn low_points(&self) -> Vec<(usize, usize)> {
         let (h, w) = self.size();
         (0..h).cartesian_product(0..w).filter(
             |(i,j)| self.risk(*i, *j).is_some()
         ).collect()
     }
 
-    fn basin_size(&self, i : usize, j : usize) -> usize {
-        let mut marked : HashSet<(usize, usize)> = HashSet::new();
-        let mut working_set : BTreeSet<(usize, usize)> = BTreeSet::new();
-        working_set.insert((i,j));
-        while let Some(elem) = working_set.pop_first() {
-            for neighbour in self.non_edge_neighbours(elem.0, elem.1) {
-                if !marked.contains(&neighbour) {
-                    working_set.insert(neighbour);
-                }
+    fn is_ridge(&self, i : usize, j : usize) -> bool {
+        self.get(i, j).map(|val| val >= self.ridge).unwrap_or(true)
+    }
+
+    // Union find over a single raster scan joining each cell with the cells
+    // above and to the left, every component of non ridge cells is a basin.
+    pub fn basin_map(&self) -> BasinMap {
+        let (h, w) = self.size();
+        let mut parents : Vec<usize> = (0..h*w).collect();
+        for (i, j) in (0..h).cartesian_product(0..w) {
+            if self.is_ridge(i, j) {
+                continue;
+            }
+            if i > 0 && !self.is_ridge(i-1, j) {
+                union(&mut parents, i*w + j, (i-1)*w + j);
+            }
+            if j > 0 && !self.is_ridge(i, j-1) {
+                union(&mut parents, i*w + j, i*w + j-1);
             }
-            marked.insert(elem);
         }
-        marked.len()
+        let mut labels : Vec<Vec<Option<usize>>> = vec!(vec!(None; w); h);
+        let mut root_ids : Vec<Option<usize>> = vec!(None; h*w);
+        let mut basins : Vec<Basin> = Vec::new();
+        for (i, j) in (0..h).cartesian_product(0..w) {
+            if self.is_ridge(i, j) {
+                continue;
+            }
+            let root = find_root(&mut parents, i*w + j);
+            let id = *root_ids[root].get_or_insert_with(|| {
+                basins.push(Basin { id : basins.len(), size : 0, low_point : (i, j), depth : 0, perimeter : 0 });
+                basins.len() - 1
+            });
+            labels[i][j] = Some(id);
+            let basin = &mut basins[id];
+            basin.size += 1;
+            let val = self.heights[i][j];
+            if val < self.heights[basin.low_point.0][basin.low_point.1] {
+                basin.low_point = (i, j);
+            }
+            basin.depth = self.ridge - self.heights[basin.low_point.0][basin.low_point.1];
+            basin.perimeter += 4 - self.non_edge_neighbours(i, j).len();
+        }
+        let mut adjacent : BTreeSet<(usize, usize)> = BTreeSet::new();
+        for (i, j) in (0..h).cartesian_product(0..w) {
+            if !self.is_ridge(i, j) {
+                continue;
+            }
+            let touching : BTreeSet<usize> = self.neighbour_indices(i, j).into_iter().filter_map(
+                |(n_i, n_j)| labels.get(n_i).and_then(|row| row.get(n_j).cloned().flatten())
+            ).collect();
+            for pair in touching.iter().cloned().tuple_combinations() {
+                adjacent.insert(pair);
+            }
+        }
+        BasinMap { labels : labels, basins : basins, adjacent : adjacent }
     }
 
     pub fn largest_basins(&self, num : usize) -> Vec<usize> {
-        self.low_points().into_iter().map(
-            |(i,j)| self.basin_size(i, j)
+        self.basin_map().basins.iter().map(
+            |basin| basin.size
         ).sorted().rev().take(num).collect()
     }
 
@@ -109,4 +262,306 @@ impl HeightMap {
             |(i,j)| self.risk(i,j)
         ).sum()
     }
-}
\ No newline at end of file
+}
+
+impl HeightMap {
+    // Only cells that exist, rows may be of different lengths
+    fn cells(&self) -> Vec<(usize, usize)> {
+        self.heights.iter().enumerate().flat_map(
+            |(i, row)| (0..row.len()).map(move |j| (i, j))
+        ).collect()
+    }
+
+    fn in_map_neighbours(&self, i : usize, j : usize) -> Vec<(usize, usize)> {
+        self.neighbour_indices(i, j).into_iter().filter(
+            |(n_i, n_j)| self.get(*n_i, *n_j).is_some()
+        ).collect()
+    }
+
+    // Next to the outside of the map, which beside a short row can be
+    // away from the border of the bounding box.
+    fn on_edge(&self, i : usize, j : usize) -> bool {
+        self.in_map_neighbours(i, j).len() < 4
+    }
+
+    fn steepest_descent(&self, i : usize, j : usize) -> Option<(usize, usize)> {
+        let val = self.heights[i][j];
+        self.in_map_neighbours(i, j).into_iter().min_by_key(
+            |(n_i, n_j)| self.heights[*n_i][*n_j]
+        ).filter(|(n_i, n_j)| self.heights[*n_i][*n_j] < val)
+    }
+
+    // Water runs down the steepest way from each cell. Across a flat it runs
+    // to the nearest edge of the flat with somewhere lower to go, and a flat
+    // with nowhere lower is a sink if it is below the ridge.
+    pub fn catchments(&self) -> Catchments {
+        let mut next : HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+        let mut sinks : Vec<(usize, usize)> = Vec::new();
+        let mut catchment : HashMap<(usize, usize), usize> = HashMap::new();
+        let mut seen : BTreeSet<(usize, usize)> = BTreeSet::new();
+        for cell in self.cells() {
+            if !seen.insert(cell) {
+                continue;
+            }
+            let val = self.heights[cell.0][cell.1];
+            let mut flat : Vec<(usize, usize)> = vec!(cell);
+            let mut index = 0;
+            while index < flat.len() {
+                let (i, j) = flat[index];
+                for neighbour in self.in_map_neighbours(i, j) {
+                    if self.heights[neighbour.0][neighbour.1] == val && seen.insert(neighbour) {
+                        flat.push(neighbour);
+                    }
+                }
+                index += 1;
+            }
+            let mut queue : VecDeque<(usize, usize)> = VecDeque::new();
+            let mut routed : BTreeSet<(usize, usize)> = BTreeSet::new();
+            for (i, j) in flat.iter() {
+                if let Some(lower) = self.steepest_descent(*i, *j) {
+                    next.insert((*i, *j), lower);
+                    queue.push_back((*i, *j));
+                    routed.insert((*i, *j));
+                }
+            }
+            if queue.is_empty() {
+                if val < self.ridge {
+                    sinks.push(cell);
+                    for flat_cell in flat {
+                        catchment.insert(flat_cell, sinks.len() - 1);
+                    }
+                }
+                continue;
+            }
+            while let Some((i, j)) = queue.pop_front() {
+                for neighbour in self.in_map_neighbours(i, j) {
+                    if self.heights[neighbour.0][neighbour.1] == val && routed.insert(neighbour) {
+                        next.insert(neighbour, (i, j));
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+        }
+        let mut cells : Vec<Vec<(usize, usize)>> = vec!(Vec::new(); sinks.len());
+        for cell in self.cells() {
+            let mut path : Vec<(usize, usize)> = Vec::new();
+            let mut current = cell;
+            while !catchment.contains_key(&current) {
+                path.push(current);
+                match next.get(&current) {
+                    Some(lower) => current = *lower,
+                    None => break
+                }
+            }
+            if let Some(id) = catchment.get(&current).cloned() {
+                for step in path {
+                    catchment.insert(step, id);
+                }
+                cells[id].push(cell);
+            }
+        }
+        Catchments { sinks : sinks, cells : cells, catchment : catchment }
+    }
+
+    // The lowest level at which water in the given cells escapes, along with
+    // the catchment it escapes into.
+    fn spill(&self, cells : &[(usize, usize)], catchments : &Catchments, members : &BTreeSet<usize>) -> (u8, Spill) {
+        let mut best : Option<(u8, Spill)> = None;
+        for (i, j) in cells.iter() {
+            let val = self.heights[*i][*j];
+            // Cells outside any catchment drain nowhere so count as leaving
+            let mut exits : Vec<(u8, Spill)> = self.in_map_neighbours(*i, *j).into_iter().filter(
+                |cell| catchments.id(cell).map(|id| !members.contains(&id)).unwrap_or(true)
+            ).map(
+                |(n_i, n_j)| (
+                    std::cmp::max(val, self.heights[n_i][n_j]),
+                    catchments.id(&(n_i, n_j)).map(Spill::Into).unwrap_or(Spill::OffMap)
+                )
+            ).collect();
+            if self.on_edge(*i, *j) {
+                exits.push((val, Spill::OffMap));
+            }
+            for exit in exits {
+                if best.map(|(height, _)| exit.0 < height).unwrap_or(true) {
+                    best = Some(exit);
+                }
+            }
+        }
+        best.unwrap_or((u8::MAX, Spill::OffMap))
+    }
+
+    fn capacity(&self, cells : &[(usize, usize)], level : u8) -> u64 {
+        cells.iter().map(
+            |(i, j)| level.saturating_sub(self.heights[*i][*j]) as u64
+        ).sum()
+    }
+
+    pub fn spill_heights(&self) -> Vec<((usize, usize), u8)> {
+        let catchments = self.catchments();
+        // Low points at or above the ridge belong to no catchment
+        self.low_points().into_iter().filter_map(
+            |low_point| {
+                let members : BTreeSet<usize> = BTreeSet::from([catchments.id(&low_point)?]);
+                let cells = catchments.cells_of(&members);
+                Some((low_point, self.spill(&cells, &catchments, &members).0))
+            }
+        ).collect()
+    }
+
+    // Water poured on a cell runs down to its sink. A lake that fills past
+    // its spill height passes the excess on to where it spills, and lakes
+    // that spill into each other once full join into a single lake.
+    pub fn rain(&self, poured : &[((usize, usize), u64)]) -> Rainfall {
+        let catchments = self.catchments();
+        let sinks = catchments.sinks();
+        let mut lake_of : Vec<usize> = (0..sinks.len()).collect();
+        let mut members : Vec<BTreeSet<usize>> = (0..sinks.len()).map(|id| BTreeSet::from([id])).collect();
+        let mut water : Vec<u64> = vec!(0; sinks.len());
+        let mut outflow = 0;
+        for (cell, amount) in poured.iter() {
+            match catchments.id(cell) {
+                Some(id) => water[id] += amount,
+                None if self.get(cell.0, cell.1).is_some() => outflow += amount,
+                None => ()
+            }
+        }
+        let lake_state = |members : &BTreeSet<usize>| -> (u8, Spill, u64) {
+            let cells = catchments.cells_of(members);
+            let (height, spill) = self.spill(&cells, &catchments, members);
+            (height, spill, self.capacity(&cells, height))
+        };
+        let mut states : Vec<(u8, Spill, u64)> = members.iter().map(|lake| lake_state(lake)).collect();
+        loop {
+            let overflowing = (0..members.len()).find(
+                |lake| !members[*lake].is_empty() && water[*lake] > states[*lake].2
+            );
+            let lake = match overflowing {
+                Some(lake) => lake,
+                None => break
+            };
+            let mut path = vec!(lake);
+            let mut target = states[lake].1;
+            let mut merged = false;
+            while let Spill::Into(id) = target {
+                let next = lake_of[id];
+                if let Some(start) = path.iter().position(|visited| *visited == next) {
+                    let cycle : Vec<usize> = path.split_off(start);
+                    let keep = cycle[0];
+                    for other in cycle.into_iter().skip(1) {
+                        let moved = std::mem::take(&mut members[other]);
+                        for id in moved.iter() {
+                            lake_of[*id] = keep;
+                        }
+                        members[keep].extend(moved);
+                        water[keep] += water[other];
+                        water[other] = 0;
+                    }
+                    states[keep] = lake_state(&members[keep]);
+                    merged = true;
+                    break;
+                }
+                if water[next] < states[next].2 {
+                    break;
+                }
+                path.push(next);
+                target = states[next].1;
+            }
+            if merged {
+                continue;
+            }
+            let excess = water[lake] - states[lake].2;
+            water[lake] = states[lake].2;
+            match target {
+                Spill::OffMap => outflow += excess,
+                Spill::Into(id) => water[lake_of[id]] += excess
+            }
+        }
+        let lakes = (0..members.len()).filter(
+            |lake| !members[*lake].is_empty()
+        ).map(
+            |lake| Lake {
+                low_points : members[lake].iter().map(|id| sinks[*id]).collect(),
+                water : water[lake],
+                capacity : states[lake].2,
+                spill_height : states[lake].0
+            }
+        ).collect();
+        Rainfall { lakes : lakes, outflow : outflow }
+    }
+
+    // Fills every lake to the brim, the level of each cell is the lowest
+    // height it could drain off the map over.
+    pub fn trapped_water(&self) -> u64 {
+        let mut levels : HashMap<(usize, usize), u8> = HashMap::new();
+        let mut queue : BinaryHeap<Reverse<(u8, (usize, usize))>> = BinaryHeap::new();
+        for (i, j) in self.cells() {
+            if self.on_edge(i, j) {
+                levels.insert((i, j), self.heights[i][j]);
+                queue.push(Reverse((self.heights[i][j], (i, j))));
+            }
+        }
+        let mut volume = 0;
+        while let Some(Reverse((level, (i, j)))) = queue.pop() {
+            for neighbour in self.in_map_neighbours(i, j) {
+                if !levels.contains_key(&neighbour) {
+                    let val = self.heights[neighbour.0][neighbour.1];
+                    let new_level = std::cmp::max(level, val);
+                    volume += (new_level - val) as u64;
+                    levels.insert(neighbour, new_level);
+                    queue.push(Reverse((new_level, neighbour)));
+                }
+            }
+        }
+        volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn map(lines : &[&str]) -> HeightMap {
+        HeightMap::from_lines(lines.iter().map(|line| line.to_string()).collect())
+    }
+
+    #[test]
+    fn flat_ridges_are_not_sinks() {
+        let heights = map(&["99999", "91919", "99999"]);
+        assert_eq!(heights.catchments().sinks(), &vec!((1, 1), (1, 3)));
+        let rainfall = heights.rain(&[((1, 1), 1)]);
+        assert_eq!(rainfall.lakes.len(), 2);
+        assert_eq!(rainfall.lakes[0].low_points, vec!((1, 1)));
+        assert_eq!(rainfall.lakes[0].water, 1);
+        assert_eq!(rainfall.outflow, 0);
+    }
+
+    #[test]
+    fn ragged_maps() {
+        let heights = map(&["123", "1", "456"]);
+        assert_eq!(heights.trapped_water(), 0);
+        assert_eq!(heights.catchments().drain_to(2, 2), Some((0, 0)));
+        assert_eq!(heights.rain(&[((2, 2), 5)]).outflow, 5);
+        assert_eq!(map(&["323", "1", "456"]).spill_heights(), vec!(((0, 1), 2), ((1, 0), 1)));
+    }
+
+    #[test]
+    fn rain_is_kept_or_flows_away() {
+        let mut rng = ChaCha8Rng::seed_from_u64(39);
+        for _ in 0..500 {
+            let lines : Vec<String> = (0..rng.gen_range(1..7)).map(
+                |_| (0..rng.gen_range(1..7)).map(|_| ['1', '2', '3', '9'][rng.gen_range(0..4)]).collect()
+            ).collect();
+            let heights = HeightMap::from_lines(lines.clone());
+            let poured : Vec<((usize, usize), u64)> = (0..rng.gen_range(0..5)).map(
+                |_| ((rng.gen_range(0..7), rng.gen_range(0..7)), rng.gen_range(0..30))
+            ).filter(|((i, j), _)| heights.get(*i, *j).is_some()).collect();
+            let rainfall = heights.rain(&poured);
+            let kept : u64 = rainfall.lakes.iter().map(|lake| lake.water).sum();
+            assert_eq!(kept + rainfall.outflow, poured.iter().map(|(_, amount)| amount).sum::<u64>(), "{:?}", lines);
+            assert!(rainfall.lakes.iter().all(|lake| lake.water <= lake.capacity), "{:?}", lines);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+pub mod sonar;
+pub mod movement;
+pub mod binary;
+pub mod bingo;
+pub mod vents;
+pub mod fish;
+pub mod crabs;
+pub mod displays;
+pub mod heights;
+pub mod brackets;
+pub mod automaton;
+pub mod octopus;
+pub mod caves;
+pub mod folding;
+pub mod ocr;
+pub mod polymer;
+pub mod pathfind;
+pub mod packet;
+pub mod shot;
+pub mod snailfish;
+pub mod scans;
+pub mod enhance;
+pub mod dice;
+pub mod cubes;
+
+pub mod transition;
+
+pub mod io;
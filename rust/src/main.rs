@@ -1,47 +1,18 @@
-#![feature(option_result_contains)]
-#![feature(map_first_last)]
-#![feature(iter_intersperse)]
-
-mod sonar;
-mod movement;
-mod binary;
-mod bingo;
-mod vents;
-mod fish;
-mod crabs;
-mod displays;
-mod heights;
-mod brackets;
-mod octopus;
-mod caves;
-mod folding;
-mod polymer;
-mod pathfind;
-mod packet;
-mod shot;
-mod snailfish;
-mod scans;
-mod enhance;
-mod dice;
-mod cubes;
-
-mod io;
-
 mod challenge {
-    use super::io as io;
-    use super::sonar as sonar;
-    use super::movement as movement;
-    use super::binary as binary;
-    use super::vents as vents;
-    use super::fish as fish;
-    use super::crabs as crabs;
-    use super::displays as displays;
-    use super::brackets as brackets;
-    use super::pathfind::Pathfinder;
-    use super::shot as shot;
-    use super::snailfish as snailfish;
-    use super::scans as scans;
-    use super::dice as dice;
+    use advent_of_code::io as io;
+    use advent_of_code::sonar as sonar;
+    use advent_of_code::movement as movement;
+    use advent_of_code::binary as binary;
+    use advent_of_code::vents as vents;
+    use advent_of_code::fish as fish;
+    use advent_of_code::crabs as crabs;
+    use advent_of_code::displays as displays;
+    use advent_of_code::brackets as brackets;
+    use advent_of_code::pathfind::Pathfinder;
+    use advent_of_code::shot as shot;
+    use advent_of_code::snailfish as snailfish;
+    use advent_of_code::scans as scans;
+    use advent_of_code::dice as dice;
 
     fn challenge_1() {
         let data = io::input_as_list(1);
@@ -82,13 +53,13 @@ mod challenge {
     }
 
     fn challenge_7() {
-        let mut data = io::input_as_game(4);
+        let data = io::input_as_game(4);
         let res = data.play_first();
         println!("{}", res);
     }
 
     fn challenge_8() {
-        let mut data = io::input_as_game(4);
+        let data = io::input_as_game(4);
         let res = data.play_last();
         println!("{}", res);
     }
@@ -107,13 +78,13 @@ mod challenge {
 
     fn challenge_11() {
         let data = io::input_as_fish(6);
-        let res = fish::count_after(&data, 79);
+        let res = fish::count_after(&data, 79, &fish::Lifecycle::lanternfish()).unwrap();
         println!("{}", res);
     }
 
     fn challenge_12() {
         let data = io::input_as_fish(6);
-        let res = fish::count_after(&data, 255);
+        let res = fish::count_after(&data, 255, &fish::Lifecycle::lanternfish()).unwrap();
         println!("{}", res);
     }
 
@@ -138,7 +109,7 @@ mod challenge {
     fn challenge_16() {
         let data = io::input_as_displays(8);
         let res : usize = data.iter().map(
-            |entry| entry.output_num()
+            |entry| entry.output_num().unwrap()
         ).sum();
         println!("{}", res);
     }
@@ -175,7 +146,7 @@ mod challenge {
 
     fn challenge_22() {
         let mut data = io::input_as_octopus_states(11);
-        let res : usize = data.simulate_till_flash();
+        let res : usize = data.simulate_till_flash(10000).unwrap();
         println!("{}", res);
     }
 
@@ -201,7 +172,10 @@ mod challenge {
     fn challenge_26() {
         let mut data = io::input_as_folding(13);
         data.fold();
-        data.display();
+        match data.code() {
+            Ok(code) => println!("{}", code),
+            Err(_) => data.display()
+        }
     }
 
     fn challenge_27() {
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+// Letters are 4 dots wide and 6 tall with a blank column between them
+const WIDTH : i64 = 4;
+const HEIGHT : i64 = 6;
+const PITCH : i64 = 5;
+
+const LETTERS : [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"])
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OcrError {
+    Empty,
+    WrongHeight(i64),
+    // The text read with '?' for each glyph that isn't a known letter
+    UnknownGlyphs { text : String, positions : Vec<usize> }
+}
+
+fn glyph_rows(dots : &HashSet<(i64, i64)>, left : i64, top : i64) -> Vec<String> {
+    (0..HEIGHT).map(
+        |y| (0..WIDTH).map(
+            |x| if dots.contains(&(left + x, top + y)) { '#' } else { '.' }
+        ).collect()
+    ).collect()
+}
+
+fn letter(rows : &[String]) -> Option<char> {
+    LETTERS.iter().find(
+        |(_, pattern)| pattern.iter().zip(rows.iter()).all(|(expected, row)| expected == row)
+    ).map(|(letter, _)| *letter)
+}
+
+// Glyphs are read left to right every five columns, lined up with x = 0
+// since letters like I don't use their first column.
+pub fn recognise(dots : &HashSet<(i64, i64)>) -> Result<String, OcrError> {
+    let leftmost = dots.iter().map(|(x, _)| *x).min().ok_or(OcrError::Empty)?;
+    let left = leftmost - leftmost.rem_euclid(PITCH);
+    let right = dots.iter().map(|(x, _)| *x).max().ok_or(OcrError::Empty)?;
+    let top = dots.iter().map(|(_, y)| *y).min().ok_or(OcrError::Empty)?;
+    let bottom = dots.iter().map(|(_, y)| *y).max().ok_or(OcrError::Empty)?;
+    if bottom - top + 1 > HEIGHT {
+        return Err(OcrError::WrongHeight(bottom - top + 1));
+    }
+    let glyphs = (right - left) / PITCH + 1;
+    let mut text = String::new();
+    let mut unknown : Vec<usize> = Vec::new();
+    for glyph in 0..glyphs {
+        let glyph_left = left + glyph * PITCH;
+        let stray = (WIDTH..PITCH).any(
+            |x| (top..(bottom+1)).any(|y| dots.contains(&(glyph_left + x, y)))
+        );
+        match letter(&glyph_rows(dots, glyph_left, top)) {
+            Some(letter) if !stray => text.push(letter),
+            _ => {
+                text.push('?');
+                unknown.push(glyph as usize);
+            }
+        }
+    }
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(OcrError::UnknownGlyphs { text : text, positions : unknown })
+    }
+}
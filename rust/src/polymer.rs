@@ -1,69 +1,85 @@
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::ops::{Add, Mul};
 
-fn combine_maps(map1 : &HashMap<char, usize>, map2 : &HashMap<char, usize>) -> HashMap<char, usize> {
-    let mut new_map = map1.clone();
-    for (k, v) in map2.iter() {
-        let counter = new_map.entry(*k).or_insert(0);
-        *counter += *v;
-    }
-    new_map
-}  
-
-#[derive(PartialEq,Eq,PartialOrd, Ord,Hash)]
-struct PairCacheKey {
-    fst : char,
-    snd : char,
-    steps : usize
-}
+use num_bigint::BigUint;
+use num_traits::{Zero, One};
 
-struct PairCache {
-    rules : Vec<Rule>,
-    cache : HashMap<PairCacheKey, HashMap<char, usize>>
+use super::transition::{Transition, Checked};
+
+// Elements are numbered so that the pair (a, b) is state a * elements + b,
+// each pair has the index of the element inserted into it if any.
+struct PairModel {
+    elements : Vec<char>,
+    index : HashMap<char, usize>,
+    insertions : Vec<Option<usize>>
 }
 
-impl PairCache {
-    fn new(rules : Vec<Rule>) -> PairCache {
-        PairCache {
-            rules : rules,
-            cache : HashMap::new()
+impl PairModel {
+    fn new(template : &str, rules : &Vec<Rule>) -> PairModel {
+        let elements : Vec<char> = template.chars().chain(
+            rules.iter().flat_map(|rule| [rule.input.0, rule.input.1, rule.output])
+        ).unique().collect();
+        let index : HashMap<char, usize> = elements.iter().enumerate().map(
+            |(i, element)| (*element, i)
+        ).collect();
+        let size = elements.len();
+        let mut insertions : Vec<Option<usize>> = vec!(None; size * size);
+        // The first rule for a pair wins
+        for rule in rules.iter().rev() {
+            insertions[index[&rule.input.0] * size + index[&rule.input.1]] = Some(index[&rule.output]);
         }
+        PairModel { elements : elements, index : index, insertions : insertions }
     }
 
-    fn count(&mut self, fst : char, snd : char, steps : usize) -> HashMap<char, usize> {
-        let key = PairCacheKey {fst : fst, snd : snd, steps : steps};
-        if let Some(cached_res) = self.cache.get(&key) {
-            return cached_res.clone();
-        }
-        let res = self.count_impl(fst, snd, steps);
-        self.cache.insert(key, res.clone());
-        res
+    fn pair(&self, fst : usize, snd : usize) -> usize {
+        fst * self.elements.len() + snd
     }
 
-    fn count_impl(&mut self, fst : char, snd : char, steps : usize) -> HashMap<char, usize> {
-        if steps > 0 {
-            if let Some(mid) = self.apply(fst, snd) {
-                let mut res = combine_maps(
-                    &self.count(fst, mid, steps - 1),
-                    &self.count(mid, snd, steps - 1),
-                );
-                // Remove overlap
-                let overlap_counter = res.entry(mid).or_insert(1);
-                *overlap_counter -= 1;
-                return res;
+    fn transition<T>(&self) -> Transition<T>
+        where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T>
+    {
+        let size = self.elements.len();
+        let mut transition = Transition::zero(size * size);
+        for fst in 0..size {
+            for snd in 0..size {
+                let pair = self.pair(fst, snd);
+                match self.insertions[pair] {
+                    Some(mid) => {
+                        transition.add_entry(self.pair(fst, mid), pair, T::one());
+                        transition.add_entry(self.pair(mid, snd), pair, T::one());
+                    },
+                    None => transition.add_entry(pair, pair, T::one())
+                }
             }
         }
-        if fst == snd {
-            HashMap::from_iter([(fst, 2)])
-        } else {
-            HashMap::from_iter([(fst, 1), (snd, 1)])
-        }
+        transition
     }
 
-    fn apply(&self, fst : char, snd : char) -> Option<char> {
-        self.rules.iter().filter_map(
-            |rule| rule.apply(fst, snd)
-        ).next()
+    // Every element is the first of a pair apart from the last one in the
+    // template, which stays at the end.
+    fn element_counts<T>(&self, template : &str, steps : u64) -> HashMap<char, T>
+        where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T>
+    {
+        let size = self.elements.len();
+        let mut pairs = vec!(T::zero(); size * size);
+        for (fst, snd) in template.chars().tuple_windows() {
+            let pair = self.pair(self.index[&fst], self.index[&snd]);
+            pairs[pair] = pairs[pair].clone() + T::one();
+        }
+        let pairs = self.transition::<T>().pow(steps).apply(&pairs);
+        let mut counts : HashMap<char, T> = HashMap::new();
+        for (pair, count) in pairs.into_iter().enumerate() {
+            if !count.is_zero() {
+                let counter = counts.entry(self.elements[pair / size]).or_insert_with(T::zero);
+                *counter = counter.clone() + count;
+            }
+        }
+        if let Some(last) = template.chars().last() {
+            let counter = counts.entry(last).or_insert_with(T::zero);
+            *counter = counter.clone() + T::one();
+        }
+        counts
     }
 }
 
@@ -86,14 +102,6 @@ impl Rule {
         }
         None
     }
-
-    fn apply(&self, fst : char, snd : char) -> Option<char> {
-        if fst == self.input.0 && snd == self.input.1 {
-            Some(self.output)
-        } else {
-            None
-        }
-    }
 }
 
 pub struct PolymerProgram {
@@ -113,25 +121,55 @@ impl PolymerProgram {
         )
     }
 
+    pub fn element_counts<T>(&self, steps : u64) -> HashMap<char, T>
+        where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T>
+    {
+        PairModel::new(&self.template, &self.rules).element_counts(&self.template, steps)
+    }
+
+    // Counts roughly double every step, so exact counts are only practical
+    // for thousands of steps. Very long runs need Checked or Modular counts.
+    pub fn element_counts_exact(&self, steps : u64) -> HashMap<char, BigUint> {
+        self.element_counts::<BigUint>(steps)
+    }
+
+    pub fn calculate_common_exact(&self, steps : u64) -> Option<(BigUint, BigUint)> {
+        let counts = self.element_counts_exact(steps);
+        Some((counts.values().max()?.clone(), counts.values().min()?.clone()))
+    }
+
+    // None if the counts don't fit in a u64
+    pub fn calculate_common_checked(&self, steps : u64) -> Option<(u64, u64)> {
+        let counts = self.element_counts::<Checked>(steps).into_values().map(
+            |count| count.0
+        ).collect::<Option<Vec<u64>>>()?;
+        Some((*counts.iter().max()?, *counts.iter().min()?))
+    }
+
     pub fn calculate_common(&self, steps : usize) -> (usize, usize) {
-        let map = self.calculate_counts(steps);
-        (*map.values().max().unwrap(), *map.values().min().unwrap())
+        let counts = self.element_counts::<u64>(steps as u64);
+        (
+            counts.values().max().cloned().unwrap_or(0) as usize,
+            counts.values().min().cloned().unwrap_or(0) as usize
+        )
     }
+}
 
-    fn calculate_counts(&self, steps : usize) -> HashMap<char, usize> {
-        let mut counter : PairCache = PairCache::new(self.rules.clone());
-        let mut counts = self.template.chars().tuple_windows().map(
-            |(fst, snd)| counter.count(fst, snd, steps)
-        ).reduce(
-            |map1, map2| combine_maps(&map1, &map2)  
-        ).unwrap();
-        // Remove overlaps
-        self.template.chars().tuple_windows().for_each(
-            |(_,mid,  _)| {
-                let overlap_counter = counts.entry(mid).or_insert(1);
-                *overlap_counter -= 1;
-            }
-        );
-        counts
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unused_overflowing_pairs_leave_checked_counts() {
+        let program = PolymerProgram::from_string("AB\n\nAB -> C\nXX -> X").unwrap();
+        assert_eq!(program.calculate_common_checked(10), Some((1, 1)));
+        assert_eq!(program.calculate_common_checked(70), Some((1, 1)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn checked_counts_overflow_to_none() {
+        let program = PolymerProgram::from_string("NN\n\nNN -> N").unwrap();
+        assert_eq!(program.calculate_common_checked(10), Some((1025, 1025)));
+        assert_eq!(program.calculate_common_checked(70), None);
+    }
+}
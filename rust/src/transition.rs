@@ -0,0 +1,158 @@
+use std::ops::{Add, Mul};
+
+use num_traits::{Zero, One};
+
+// Counts that become None once they no longer fit in a u64
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checked(pub Option<u64>);
+
+// Counts modulo M, for when only a remainder of a huge count is needed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Modular<const M : u64>(pub u64);
+
+impl Add for Checked {
+    type Output = Checked;
+
+    fn add(self, other : Checked) -> Checked {
+        Checked(self.0.and_then(|value| value.checked_add(other.0?)))
+    }
+}
+
+// Nothing times an overflowed count is still nothing
+impl Mul for Checked {
+    type Output = Checked;
+
+    fn mul(self, other : Checked) -> Checked {
+        if self.is_zero() || other.is_zero() {
+            return Checked::zero();
+        }
+        Checked(self.0.and_then(|value| value.checked_mul(other.0?)))
+    }
+}
+
+impl From<u64> for Checked {
+    fn from(value : u64) -> Checked {
+        Checked(Some(value))
+    }
+}
+
+impl Zero for Checked {
+    fn zero() -> Checked {
+        Checked(Some(0))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == Some(0)
+    }
+}
+
+impl One for Checked {
+    fn one() -> Checked {
+        Checked(Some(1))
+    }
+}
+
+impl<const M : u64> Add for Modular<M> {
+    type Output = Modular<M>;
+
+    fn add(self, other : Modular<M>) -> Modular<M> {
+        Modular(((self.0 as u128 + other.0 as u128) % M as u128) as u64)
+    }
+}
+
+impl<const M : u64> Mul for Modular<M> {
+    type Output = Modular<M>;
+
+    fn mul(self, other : Modular<M>) -> Modular<M> {
+        Modular(((self.0 as u128 * other.0 as u128) % M as u128) as u64)
+    }
+}
+
+impl<const M : u64> Zero for Modular<M> {
+    fn zero() -> Modular<M> {
+        Modular(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl<const M : u64> One for Modular<M> {
+    fn one() -> Modular<M> {
+        Modular(1 % M)
+    }
+}
+
+// A square matrix taking counts in each state to counts after one step, so
+// many steps are a matrix power.
+#[derive(Clone, Debug)]
+pub struct Transition<T> {
+    matrix : Vec<Vec<T>>
+}
+
+impl<T> Transition<T>
+    where T : Clone + Zero + One + Add<Output = T> + Mul<Output = T>
+{
+    pub fn zero(states : usize) -> Transition<T> {
+        Transition { matrix : vec!(vec!(T::zero(); states); states) }
+    }
+
+    pub fn identity(states : usize) -> Transition<T> {
+        let mut transition = Transition::zero(states);
+        for i in 0..states {
+            transition.matrix[i][i] = T::one();
+        }
+        transition
+    }
+
+    pub fn add_entry(&mut self, to : usize, from : usize, amount : T) {
+        self.matrix[to][from] = self.matrix[to][from].clone() + amount;
+    }
+
+    pub fn states(&self) -> usize {
+        self.matrix.len()
+    }
+
+    pub fn multiply(&self, other : &Transition<T>) -> Transition<T> {
+        let states = self.states();
+        Transition {
+            matrix : (0..states).map(
+                |i| (0..states).map(
+                    |j| (0..states).filter(
+                        |k| !self.matrix[i][*k].is_zero() && !other.matrix[*k][j].is_zero()
+                    ).fold(
+                        T::zero(),
+                        |acc, k| acc + self.matrix[i][k].clone() * other.matrix[k][j].clone()
+                    )
+                ).collect()
+            ).collect()
+        }
+    }
+
+    pub fn pow(&self, mut exponent : u64) -> Transition<T> {
+        let mut result = Transition::identity(self.states());
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = result.multiply(&base);
+            }
+            exponent /= 2;
+            if exponent > 0 {
+                base = base.multiply(&base);
+            }
+        }
+        result
+    }
+
+    pub fn apply(&self, counts : &Vec<T>) -> Vec<T> {
+        self.matrix.iter().map(
+            |row| row.iter().zip(counts.iter()).filter(
+                |(factor, count)| !factor.is_zero() && !count.is_zero()
+            ).fold(
+                T::zero(),
+                |acc, (factor, count)| acc + factor.clone() * count.clone()
+            )
+        ).collect()
+    }
+}
@@ -1,4 +1,7 @@
-use itertools::{iproduct};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use itertools::{iproduct, Itertools};
 
 #[derive(Clone, Debug)]
 pub struct Vent {
@@ -6,6 +9,11 @@ pub struct Vent {
     end : (i64, i64)
 }
 
+pub struct VentField {
+    vents : Vec<Vent>,
+    coverage : HashMap<(i64, i64), usize>
+}
+
 fn parse_pos(string : &str) -> Option<(i64, i64)> {
     if let Some( (x_str , y_str) ) = string.split_once(",") {
         if let Ok(x) = x_str.parse::<i64>() {
@@ -33,26 +41,109 @@ impl Vent {
         self.start.0 == self.end.0 || self.start.1 == self.end.1
     }
 
-    fn hits_point(&self, x : i64, y : i64) -> bool {
-        if self.start.0 == self.end.0 {
-            let min_y = std::cmp::min(self.start.1, self.end.1);
-            let max_y = std::cmp::max(self.start.1, self.end.1);
-            return x == self.start.0 && y <= max_y && y >= min_y; 
+    fn y_range(&self) -> (i64, i64) {
+        (std::cmp::min(self.start.1, self.end.1), std::cmp::max(self.start.1, self.end.1))
+    }
+
+    fn x_range(&self) -> (i64, i64) {
+        (std::cmp::min(self.start.0, self.end.0), std::cmp::max(self.start.0, self.end.0))
+    }
+
+    fn delta(&self) -> (i64, i64) {
+        (self.end.0 - self.start.0, self.end.1 - self.start.1)
+    }
+
+    fn lattice_step(&self) -> ((i64, i64), i64) {
+        let (delta_x, delta_y) = self.delta();
+        let steps = gcd(delta_x.abs(), delta_y.abs());
+        if steps == 0 {
+            ((0, 0), 0)
+        } else {
+            ((delta_x / steps, delta_y / steps), steps)
+        }
+    }
+
+    pub fn points(&self) -> Vec<(i64, i64)> {
+        let ((step_x, step_y), steps) = self.lattice_step();
+        (0..(steps+1)).map(
+            |k| (self.start.0 + k * step_x, self.start.1 + k * step_y)
+        ).collect()
+    }
+
+    fn row_interval(&self, y : i64) -> Option<(i64, i64)> {
+        let (min_y, max_y) = self.y_range();
+        if y < min_y || y > max_y {
+            return None;
         }
-        if self.start.0 < self.end.0 {
-            let delta_x = x - self.start.0;
-            let delta_y = ((self.end.1 - self.start.1) * delta_x) / (self.end.0 - self.start.0);
-            let interp_y = self.start.1 + delta_y;
-            x >= self.start.0 && x <= self.end.0 && interp_y == y
+        let (delta_x, delta_y) = self.delta();
+        if delta_y == 0 {
+            Some(self.x_range())
+        } else if ((y - self.start.1) * delta_x) % delta_y == 0 {
+            let x = self.start.0 + ((y - self.start.1) * delta_x) / delta_y;
+            Some((x, x))
         } else {
-            let delta_x = x - self.end.0;
-            let delta_y = ((self.start.1 - self.end.1) * delta_x) / (self.start.0 - self.end.0);
-            let interp_y = self.end.1 + delta_y;
-            x >= self.end.0 && x <= self.start.0 && interp_y == y
+            None
+        }
+    }
+
+    pub fn hits_point(&self, x : i64, y : i64) -> bool {
+        let (min_x, max_x) = self.x_range();
+        let (min_y, max_y) = self.y_range();
+        let in_bounds = x >= min_x && x <= max_x && y >= min_y && y <= max_y;
+        in_bounds && cross(self.delta(), (x - self.start.0, y - self.start.1)) == 0
+    }
+
+    pub fn intersection(&self, other : &Vent) -> Vec<(i64, i64)> {
+        let ((step_x, step_y), steps) = self.lattice_step();
+        if steps == 0 {
+            return if other.hits_point(self.start.0, self.start.1) {
+                vec!(self.start)
+            } else {
+                Vec::new()
+            };
+        }
+        if other.lattice_step().1 == 0 {
+            return other.intersection(self);
+        }
+        let offset = (other.start.0 - self.start.0, other.start.1 - self.start.1);
+        let denom = cross(self.delta(), other.delta());
+        if denom == 0 {
+            if cross(self.delta(), offset) != 0 {
+                return Vec::new();
+            }
+            // Collinear so both segments share the same lattice, measure the
+            // other end points in steps along this one
+            let norm = step_x * step_x + step_y * step_y;
+            let param = |(x, y) : (i64, i64)| ((x - self.start.0) * step_x + (y - self.start.1) * step_y) / norm;
+            let (a, b) = (param(other.start), param(other.end));
+            let lower = std::cmp::max(0, std::cmp::min(a, b));
+            let upper = std::cmp::min(steps, std::cmp::max(a, b));
+            return (lower..(upper+1)).map(
+                |k| (self.start.0 + k * step_x, self.start.1 + k * step_y)
+            ).collect();
+        }
+        let t_num = cross(offset, other.delta());
+        let u_num = cross(offset, self.delta());
+        let within = |num : i64| if denom > 0 { num >= 0 && num <= denom } else { num <= 0 && num >= denom };
+        if !within(t_num) || !within(u_num) {
+            return Vec::new();
         }
+        let (delta_x, delta_y) = self.delta();
+        if (t_num * delta_x) % denom != 0 || (t_num * delta_y) % denom != 0 {
+            return Vec::new();
+        }
+        vec!((self.start.0 + (t_num * delta_x) / denom, self.start.1 + (t_num * delta_y) / denom))
     }
 }
 
+fn gcd(a : i64, b : i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn cross(a : (i64, i64), b : (i64, i64)) -> i64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
 fn x_range(vents : &Vec<Vent>) -> (i64, i64) {
     let x_min = vents.iter().map(
         |vent| std::cmp::min(vent.start.0, vent.end.0)
@@ -79,7 +170,66 @@ fn has_overlap(vents : &Vec<Vent>, x: i64, y: i64) -> bool {
      ).count() > 1
 }
 
+fn row_overlap(intervals : &mut Vec<(i64, i64)>) -> usize {
+    let mut events : Vec<(i64, i64)> = intervals.drain(..).flat_map(
+        |(x_min, x_max)| [(x_min, 1), (x_max+1, -1)]
+    ).collect();
+    events.sort_unstable();
+    let mut overlap = 0;
+    let mut coverage = 0;
+    let mut last_x = 0;
+    for (x, change) in events {
+        if coverage > 1 {
+            overlap += (x - last_x) as usize;
+        }
+        coverage += change;
+        last_x = x;
+    }
+    overlap
+}
+
 pub fn get_overlap_num(vents : &Vec<Vent>) -> usize {
+    let mut by_start : Vec<&Vent> = vents.iter().collect();
+    by_start.sort_by_key(|vent| vent.y_range().0);
+    let mut pending = by_start.into_iter().peekable();
+    let mut active : Vec<&Vent> = Vec::new();
+    let mut intervals : Vec<(i64, i64)> = Vec::new();
+    let mut overlap = 0;
+    let mut y = match pending.peek() {
+        Some(vent) => vent.y_range().0,
+        None => return 0
+    };
+    loop {
+        while let Some(vent) = pending.next_if(|vent| vent.y_range().0 <= y) {
+            active.push(vent);
+        }
+        active.retain(|vent| vent.y_range().1 >= y);
+        if active.is_empty() {
+            match pending.peek() {
+                Some(vent) => y = vent.y_range().0,
+                None => return overlap
+            }
+            continue;
+        }
+        intervals.extend(active.iter().filter_map(
+            |vent| vent.row_interval(y)
+        ));
+        overlap += row_overlap(&mut intervals);
+        y += 1;
+    }
+}
+
+pub fn get_overlap_num_pairs(vents : &Vec<Vent>) -> usize {
+    let overlaps : HashSet<(i64, i64)> = vents.iter().tuple_combinations().flat_map(
+        |(a, b)| a.intersection(b)
+    ).collect();
+    overlaps.len()
+}
+
+pub fn get_overlap_num_brute(vents : &Vec<Vent>) -> usize {
+    if vents.is_empty() {
+        return 0;
+    }
     let (x_min, x_max) = x_range(vents);
     let (y_min, y_max) = y_range(vents);
     iproduct!(x_min..(x_max+1), y_min..(y_max+1)).filter(
@@ -92,4 +242,175 @@ pub fn get_overlap_num_cardinal(vents : &Vec<Vent>) -> usize {
         |vent| vent.is_cardinal()
     ).cloned().collect();
     get_overlap_num(&filtered)
-}
\ No newline at end of file
+}
+
+impl VentField {
+    pub fn from_vents(vents : &Vec<Vent>) -> VentField {
+        let mut coverage : HashMap<(i64, i64), usize> = HashMap::new();
+        for point in vents.iter().flat_map(|vent| vent.points()) {
+            *coverage.entry(point).or_insert(0) += 1;
+        }
+        VentField { vents : vents.clone(), coverage : coverage }
+    }
+
+    pub fn coverage(&self, x : i64, y : i64) -> usize {
+        self.coverage.get(&(x,y)).cloned().unwrap_or(0)
+    }
+
+    pub fn points_at_least(&self, min_coverage : usize) -> Vec<(i64, i64)> {
+        self.coverage.iter().filter(
+            |(_, count)| **count >= min_coverage
+        ).map(
+            |(point, _)| *point
+        ).sorted().collect()
+    }
+
+    pub fn overlap_num(&self) -> usize {
+        self.coverage.values().filter(|count| **count > 1).count()
+    }
+
+    pub fn most_dangerous(&self) -> Option<((i64, i64), usize)> {
+        self.coverage.iter().map(
+            |(point, count)| (*point, *count)
+        ).min_by_key(
+            |(point, count)| (std::cmp::Reverse(*count), *point)
+        )
+    }
+
+    pub fn vents_through(&self, x : i64, y : i64) -> Vec<&Vent> {
+        self.vents.iter().filter(
+            |vent| vent.hits_point(x, y)
+        ).collect()
+    }
+
+    fn bounds(&self) -> Option<((i64, i64), (i64, i64))> {
+        if self.vents.is_empty() {
+            None
+        } else {
+            Some((x_range(&self.vents), y_range(&self.vents)))
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let rows : String = self.points_at_least(1).into_iter().map(
+            |(x, y)| format!("{},{},{}\n", x, y, self.coverage(x, y))
+        ).collect();
+        format!("x,y,coverage\n{}", rows)
+    }
+
+    // Plain greyscale PGM, one pixel per point of the bounding box, with
+    // brighter pixels for more vents. The format needs a maximum of at
+    // least one even when nothing is covered.
+    pub fn to_pgm(&self) -> String {
+        let max_coverage = std::cmp::max(1, self.coverage.values().max().cloned().unwrap_or(0));
+        if let Some(((x_min, x_max), (y_min, y_max))) = self.bounds() {
+            let rows : String = (y_min..(y_max+1)).map(
+                |y| (x_min..(x_max+1)).map(
+                    |x| self.coverage(x, y).to_string()
+                ).join(" ") + "\n"
+            ).collect();
+            format!("P2\n{} {}\n{}\n{}", x_max - x_min + 1, y_max - y_min + 1, max_coverage, rows)
+        } else {
+            format!("P2\n0 0\n{}\n", max_coverage)
+        }
+    }
+
+    pub fn write_csv(&self, path : &str) -> std::io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+
+    pub fn write_pgm(&self, path : &str) -> std::io::Result<()> {
+        fs::write(path, self.to_pgm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn vent(start : (i64, i64), end : (i64, i64)) -> Vent {
+        Vent { start : start, end : end }
+    }
+
+    // Horizontal, vertical and 45 degree vents, with single points mixed in
+    fn random_vents(rng : &mut ChaCha8Rng, count : usize) -> Vec<Vent> {
+        (0..count).map(|_| {
+            let start = (rng.gen_range(0..20), rng.gen_range(0..20));
+            let length = rng.gen_range(0..10);
+            let (step_x, step_y) = [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (0, -1), (-1, -1), (-1, 1)][rng.gen_range(0..8)];
+            vent(start, (start.0 + length * step_x, start.1 + length * step_y))
+        }).collect()
+    }
+
+    #[test]
+    fn sweep_matches_brute_force() {
+        let mut rng = ChaCha8Rng::seed_from_u64(29);
+        for _ in 0..300 {
+            let count = rng.gen_range(1..12);
+            let vents = random_vents(&mut rng, count);
+            assert_eq!(get_overlap_num(&vents), get_overlap_num_brute(&vents), "{:?}", vents);
+        }
+    }
+
+    #[test]
+    fn sweep_of_no_vents() {
+        assert_eq!(get_overlap_num(&Vec::new()), 0);
+        assert_eq!(get_overlap_num_brute(&Vec::new()), 0);
+    }
+
+    #[test]
+    fn example_overlaps() {
+        let vents : Vec<Vent> = [
+            "0,9 -> 5,9", "8,0 -> 0,8", "9,4 -> 3,4", "2,2 -> 2,1", "7,0 -> 7,4",
+            "6,4 -> 2,0", "0,9 -> 2,9", "3,4 -> 1,4", "0,0 -> 8,8", "5,5 -> 8,2"
+        ].iter().map(|line| Vent::from_string(line).unwrap()).collect();
+        assert_eq!(get_overlap_num_cardinal(&vents), 5);
+        assert_eq!(get_overlap_num(&vents), 12);
+    }
+
+    #[test]
+    fn overlaps_agree_for_any_slope() {
+        let mut rng = ChaCha8Rng::seed_from_u64(30);
+        for _ in 0..300 {
+            let count = rng.gen_range(1..10);
+            let vents : Vec<Vent> = (0..count).map(
+                |_| vent((rng.gen_range(0..15), rng.gen_range(0..15)), (rng.gen_range(0..15), rng.gen_range(0..15)))
+            ).collect();
+            let brute = get_overlap_num_brute(&vents);
+            assert_eq!(get_overlap_num(&vents), brute, "{:?}", vents);
+            assert_eq!(get_overlap_num_pairs(&vents), brute, "{:?}", vents);
+        }
+    }
+
+    #[test]
+    fn intersection_is_shared_lattice_points() {
+        let mut rng = ChaCha8Rng::seed_from_u64(300);
+        for _ in 0..1000 {
+            let a = vent((rng.gen_range(0..12), rng.gen_range(0..12)), (rng.gen_range(0..12), rng.gen_range(0..12)));
+            let b = vent((rng.gen_range(0..12), rng.gen_range(0..12)), (rng.gen_range(0..12), rng.gen_range(0..12)));
+            let shared : Vec<(i64, i64)> = a.points().into_iter().filter(
+                |(x, y)| b.hits_point(*x, *y)
+            ).sorted().collect();
+            let found : Vec<(i64, i64)> = a.intersection(&b).into_iter().sorted().collect();
+            assert_eq!(found, shared, "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn field_overlaps_match_sweep() {
+        let mut rng = ChaCha8Rng::seed_from_u64(31);
+        for _ in 0..100 {
+            let count = rng.gen_range(0..10);
+            let vents = random_vents(&mut rng, count);
+            assert_eq!(VentField::from_vents(&vents).overlap_num(), get_overlap_num(&vents));
+        }
+    }
+
+    #[test]
+    fn empty_field_is_valid_pgm() {
+        assert_eq!(VentField::from_vents(&Vec::new()).to_pgm(), "P2\n0 0\n1\n");
+    }
+}